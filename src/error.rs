@@ -0,0 +1,78 @@
+use std::{error::Error, fmt, io};
+
+/// Signature or checksum did not verify
+pub const EXIT_VERIFY_FAILED: u8 = 1;
+/// Usage errors (clap uses the same code) and anything not classified below
+pub const EXIT_ERROR: u8 = 2;
+pub const EXIT_INVALID_KEY: u8 = 3;
+pub const EXIT_INVALID_SIGNATURE: u8 = 4;
+pub const EXIT_IO: u8 = 5;
+
+/// Errors scripts need to tell apart, each maps to its own exit code
+#[derive(Debug)]
+pub enum CliError {
+    VerificationFailed,
+    /// the key could not be parsed, unlocked or does not match
+    InvalidKey(anyhow::Error),
+    /// the signature is not valid base64 or has the wrong length or layout
+    InvalidSignature(anyhow::Error),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::VerificationFailed => EXIT_VERIFY_FAILED,
+            CliError::InvalidKey(_) => EXIT_INVALID_KEY,
+            CliError::InvalidSignature(_) => EXIT_INVALID_SIGNATURE,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::VerificationFailed => write!(f, "verification failed"),
+            CliError::InvalidKey(e) | CliError::InvalidSignature(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for CliError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CliError::VerificationFailed => None,
+            // transparent, the wrapped error is already displayed
+            CliError::InvalidKey(e) | CliError::InvalidSignature(e) => e.source(),
+        }
+    }
+}
+
+/// Exit code for an error returned by a command, the outermost known cause wins
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<CliError>() {
+            return e.exit_code();
+        }
+        if cause.is::<io::Error>() {
+            return EXIT_IO;
+        }
+    }
+    EXIT_ERROR
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let err = anyhow::Error::from(CliError::VerificationFailed);
+        assert_eq!(exit_code(&err), EXIT_VERIFY_FAILED);
+        let err = CliError::InvalidKey(anyhow::anyhow!("bad key"));
+        assert_eq!(err.to_string(), "bad key");
+        assert_eq!(exit_code(&err.into()), EXIT_INVALID_KEY);
+        let err = anyhow::Error::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(exit_code(&err.context("reading key")), EXIT_IO);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), EXIT_ERROR);
+    }
+}
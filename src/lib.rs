@@ -1,12 +1,20 @@
+mod error;
 mod opts;
 mod process;
 mod utils;
 
 use enum_dispatch::enum_dispatch;
+pub use error::*;
 pub use opts::{
-    Base64SubCommand, CsvOpts, DecodeOpts, EncodeOpts, GenPassOpts, HttpServeOpts, HttpSubCommand,
-    KeyGenerateOpts, Opts, SubCommand, TextSignFormat, TextSignOpts, TextSubCommand,
-    TextVerifyOpts,
+    BaoDecodeOpts, BaoEncodeOpts, BaoSliceOpts, BaoSubCommand, Base64SubCommand, CsvOpts,
+    DecodeOpts, EncodeOpts, GenPassOpts, GenPassSubCommand, HashAlgorithm, HashOpts, HttpServeOpts,
+    HttpSubCommand, JwtAlgorithm, JwtDecodeOpts, JwtSignOpts, JwtSubCommand, JwtVerifyOpts,
+    KeyEncoding, KeyGenerateOpts, KeyInfoOpts, KeyPubOpts, KeySubCommand, KeyType, KeyringAddOpts,
+    KeyringDefaultOpts, KeyringExportOpts, KeyringListOpts, KeyringRemoveOpts, KeyringSubCommand,
+    Opts, PassCheckOpts, ShareEncoding, SubCommand, TextCipher, TextCombineOpts, TextDecryptOpts,
+    TextEncryptOpts, TextSignFormat, TextSignOpts, TextSignTreeOpts, TextSplitOpts,
+    TextSshSignOpts, TextSshVerifyOpts, TextSubCommand, TextVerifyOpts, TextVerifyTreeOpts,
+    TextX25519Opts, TokenOpts, TokenSubCommand, TokenVerifyOpts,
 };
 pub use process::*;
 pub use utils::*;
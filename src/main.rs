@@ -1,11 +1,18 @@
 // rcli csv -i input.csv -o output.json --header -d ','
+use std::process::ExitCode;
+
 use clap::Parser;
-use rcli::{CmdExecutor, Opts};
+use rcli::{exit_code, CmdExecutor, Opts};
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     tracing_subscriber::fmt::init();
     let opts = Opts::parse();
-    opts.cmd.execute().await?;
-    Ok(())
+    match opts.cmd.execute().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
 }
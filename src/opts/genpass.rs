@@ -1,12 +1,54 @@
-use clap::Args;
+use std::{fmt, str::FromStr};
 
-use crate::{process_genpass, CmdExecutor};
-use zxcvbn::zxcvbn;
+use clap::{Args, Subcommand};
+use enum_dispatch::enum_dispatch;
+
+use crate::{
+    process_genpass_batch, process_genpass_output, process_pass_check, process_token_generate,
+    process_token_verify, read_secret, CliError, CmdExecutor, PasswordMode, PasswordPolicy,
+    PwnedPasswords,
+};
+
+use super::verify_file;
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenPassSubCommand {
+    #[command(about = "Analyze the strength of an existing password")]
+    Check(PassCheckOpts),
+    #[command(
+        about = "Generate an API token with a prefix and checksum",
+        args_conflicts_with_subcommands = true
+    )]
+    Token(TokenOpts),
+}
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum TokenSubCommand {
+    #[command(about = "Verify the checksum of an API token offline")]
+    Verify(TokenVerifyOpts),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GenPassOutputFormat {
+    Plain,
+    Json,
+    Csv,
+}
 
 #[derive(Debug, Args)]
 pub struct GenPassOpts {
-    #[arg(short, long, default_value_t = 16)]
-    pub length: u8,
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+    #[arg(
+        short,
+        long,
+        default_value_t = 16,
+        conflicts_with = "pattern",
+        help = "Password length, a pattern sets its own"
+    )]
+    pub length: usize,
     // the default action of bool value is SetTrue
     #[arg(long, action = clap::ArgAction::Set, default_value_t = true)]
     pub uppercase: bool,
@@ -16,21 +58,198 @@ pub struct GenPassOpts {
     pub number: bool,
     #[arg(long, action = clap::ArgAction::Set, default_value_t = true)]
     pub symbol: bool,
+    #[arg(long, help = "Minimum number of uppercase characters")]
+    pub min_upper: Option<usize>,
+    #[arg(long, help = "Minimum number of lowercase characters")]
+    pub min_lower: Option<usize>,
+    #[arg(long, help = "Minimum number of digits")]
+    pub min_numbers: Option<usize>,
+    #[arg(long, help = "Minimum number of symbols")]
+    pub min_symbols: Option<usize>,
+    #[arg(long, default_value = "", help = "Characters that must not appear")]
+    pub exclude: String,
+    #[arg(long, help = "Fully custom alphabet to pick characters from")]
+    pub charset: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["charset", "pattern"],
+        help = "Alternate consonants and vowels so the password is easy to type"
+    )]
+    pub pronounceable: bool,
+    #[arg(
+        long,
+        conflicts_with = "charset",
+        help = "Template such as \"Cvccvc-99-Cvccvc\": C/c consonant, V/v vowel, A/a letter, 9 number, # symbol, * any, \\ escapes"
+    )]
+    pub pattern: Option<String>,
+    #[arg(long, help = "Allow look-alike characters such as 0, O, l and I")]
+    pub allow_ambiguous: bool,
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+    #[arg(long, value_parser = parse_format, default_value = "plain")]
+    pub format: GenPassOutputFormat,
+    #[arg(long, value_parser = verify_file, help = "Local Pwned Passwords dump (sorted SHA1:count)")]
+    pub pwned_file: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct PassCheckOpts {
+    #[arg(
+        short,
+        long = "user-input",
+        help = "Username, email or other words the password should not be based on"
+    )]
+    pub user_inputs: Vec<String>,
+    #[arg(long)]
+    pub json: bool,
+    #[arg(long, value_parser = verify_file, help = "Local Pwned Passwords dump (sorted SHA1:count)")]
+    pub pwned_file: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct TokenOpts {
+    #[command(subcommand)]
+    pub cmd: Option<TokenSubCommand>,
+    #[arg(short, long, default_value = "rcli")]
+    pub prefix: String,
+    #[arg(
+        short,
+        long,
+        default_value_t = 32,
+        help = "Bytes of randomness in the token"
+    )]
+    pub bytes: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct TokenVerifyOpts {
+    pub token: String,
+}
+
+impl From<&GenPassOpts> for PasswordPolicy {
+    fn from(opts: &GenPassOpts) -> Self {
+        let mode = match (&opts.pattern, opts.pronounceable) {
+            (Some(pattern), _) => PasswordMode::Pattern(pattern.clone()),
+            (None, true) => PasswordMode::Pronounceable,
+            (None, false) => PasswordMode::Random,
+        };
+        Self {
+            mode,
+            length: opts.length,
+            upper: opts.uppercase,
+            lower: opts.lowercase,
+            number: opts.number,
+            symbol: opts.symbol,
+            min_upper: opts.min_upper,
+            min_lower: opts.min_lower,
+            min_numbers: opts.min_numbers,
+            min_symbols: opts.min_symbols,
+            exclude: opts.exclude.clone(),
+            charset: opts.charset.clone(),
+            allow_ambiguous: opts.allow_ambiguous,
+        }
+    }
 }
 
 impl CmdExecutor for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbol,
-        )?;
-        println!("{}", password);
-
-        let estimate = zxcvbn(&password, &[]).unwrap();
-        eprintln!("password strength score: {}", estimate.score());
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+
+        let pwned = self
+            .pwned_file
+            .as_ref()
+            .map(PwnedPasswords::open)
+            .transpose()?;
+        let reports = process_genpass_batch(&(&self).into(), self.count, pwned.as_ref())?;
+        println!("{}", process_genpass_output(&reports, self.format)?);
+
+        if let GenPassOutputFormat::Plain = self.format {
+            for report in &reports {
+                eprintln!(
+                    "password strength score: {}, entropy: {:.1} bits",
+                    report.score, report.entropy_bits
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for PassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let password = read_secret("Password: ")?;
+        let pwned = self
+            .pwned_file
+            .as_ref()
+            .map(PwnedPasswords::open)
+            .transpose()?;
+        let analysis = process_pass_check(&password, &self.user_inputs, pwned.as_ref())?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&analysis)?);
+        } else {
+            print!("{}", analysis);
+        }
+        if let Some(count @ 1..) = analysis.pwned_count {
+            anyhow::bail!("password found {} times in breached passwords", count);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TokenOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+
+        let token = process_token_generate(&self.prefix, self.bytes)?;
+        println!("{}", token);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TokenVerifyOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if !process_token_verify(&self.token)? {
+            println!("token checksum invalid");
+            return Err(CliError::VerificationFailed.into());
+        }
+        println!("token checksum valid");
         Ok(())
     }
 }
+
+fn parse_format(format: &str) -> anyhow::Result<GenPassOutputFormat> {
+    format.parse()
+}
+
+impl From<GenPassOutputFormat> for &'static str {
+    fn from(value: GenPassOutputFormat) -> Self {
+        match value {
+            GenPassOutputFormat::Plain => "plain",
+            GenPassOutputFormat::Json => "json",
+            GenPassOutputFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for GenPassOutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plain" => Ok(GenPassOutputFormat::Plain),
+            "json" => Ok(GenPassOutputFormat::Json),
+            "csv" => Ok(GenPassOutputFormat::Csv),
+            v => anyhow::bail!("Unsupported format: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for GenPassOutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fmt, str::FromStr};
+
+use clap::Args;
+
+use crate::{process_hash, process_hash_check, CheckStatus, CliError, CmdExecutor};
+
+#[derive(Debug, Args)]
+pub struct HashOpts {
+    #[arg(
+        default_value = "-",
+        help = "Files to hash, - for stdin; with --check, the checksum files"
+    )]
+    pub files: Vec<String>,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_hash_algorithm,
+        help = "blake3, sha256, sha512, sha1, md5 or xxh3 [default: sha256, or detected with --check]"
+    )]
+    pub algo: Option<HashAlgorithm>,
+    #[arg(short, long, help = "Read checksums from the files and check them")]
+    pub check: bool,
+    #[arg(long, help = "Don't print OK for each successfully verified file")]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+    Sha512,
+    Sha1,
+    Md5,
+    Xxh3,
+}
+
+fn parse_hash_algorithm(algo: &str) -> anyhow::Result<HashAlgorithm> {
+    algo.parse()
+}
+
+impl From<HashAlgorithm> for &str {
+    fn from(value: HashAlgorithm) -> Self {
+        match value {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "blake3" | "b3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "md5" => Ok(HashAlgorithm::Md5),
+            "xxh3" => Ok(HashAlgorithm::Xxh3),
+            _ => anyhow::bail!("Unsupported hash algorithm: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl CmdExecutor for HashOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if !self.check {
+            let algo = self.algo.unwrap_or(HashAlgorithm::Sha256);
+            for line in process_hash(&self.files, algo)? {
+                println!("{}", line);
+            }
+            return Ok(());
+        }
+
+        let (mut failed, mut unreadable) = (0, 0);
+        for checksum_file in &self.files {
+            for result in process_hash_check(checksum_file, self.algo)? {
+                match result.status {
+                    CheckStatus::Ok if self.quiet => {}
+                    CheckStatus::Ok => println!("{}: OK", result.name),
+                    CheckStatus::Failed => {
+                        failed += 1;
+                        println!("{}: FAILED", result.name);
+                    }
+                    CheckStatus::Unreadable => {
+                        unreadable += 1;
+                        println!("{}: FAILED open or read", result.name);
+                    }
+                }
+            }
+        }
+        if unreadable > 0 {
+            eprintln!("WARNING: {} listed files could not be read", unreadable);
+        }
+        if failed > 0 {
+            eprintln!("WARNING: {} computed checksums did NOT match", failed);
+        }
+        if failed + unreadable > 0 {
+            return Err(CliError::VerificationFailed.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hash_algorithm() {
+        assert_eq!(
+            parse_hash_algorithm("SHA256").unwrap(),
+            HashAlgorithm::Sha256
+        );
+        assert_eq!(parse_hash_algorithm("b3").unwrap(), HashAlgorithm::Blake3);
+        assert_eq!(HashAlgorithm::Xxh3.to_string(), "xxh3");
+        assert!(parse_hash_algorithm("crc32").is_err());
+    }
+}
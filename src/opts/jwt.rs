@@ -0,0 +1,227 @@
+use std::{fmt, io::Read, str::FromStr, time::Duration};
+
+use clap::{Args, Subcommand};
+use enum_dispatch::enum_dispatch;
+use serde_json::Value;
+
+use crate::{
+    get_reader, process_jwt_decode, process_jwt_sign, process_jwt_verify, CmdExecutor, JwtClaims,
+    JwtValidation,
+};
+
+use super::{text::PassphraseOpts, verify_file};
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum JwtSubCommand {
+    #[command(about = "Sign a JWT")]
+    Sign(JwtSignOpts),
+    #[command(about = "Verify a JWT and its exp, nbf, aud and iss claims")]
+    Verify(JwtVerifyOpts),
+    #[command(about = "Print the header and claims of a JWT without verifying it")]
+    Decode(JwtDecodeOpts),
+}
+
+#[derive(Debug, Args)]
+pub struct JwtSignOpts {
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        help = "HMAC secret for HS256, or an Ed25519, P-256 or RSA private key"
+    )]
+    pub key: String,
+    #[arg(
+        long,
+        default_value = "HS256",
+        value_parser = parse_jwt_algorithm,
+        help = "HS256, EdDSA, ES256 or RS256; RS256 signing is not constant time (RUSTSEC-2023-0071)"
+    )]
+    pub alg: JwtAlgorithm,
+    #[arg(long)]
+    pub sub: Option<String>,
+    #[arg(long)]
+    pub aud: Option<String>,
+    #[arg(long)]
+    pub iss: Option<String>,
+    #[arg(long, value_parser = parse_duration, help = "Lifetime, e.g. 15m, 12h or 14d")]
+    pub exp: Option<Duration>,
+    #[arg(long, value_parser = parse_duration, help = "Delay before the token becomes valid")]
+    pub nbf: Option<Duration>,
+    #[arg(
+        long = "claim",
+        value_name = "KEY=VALUE",
+        value_parser = parse_claim,
+        help = "Extra claim, repeatable; VALUE is used as JSON when it parses as JSON"
+    )]
+    pub claims: Vec<(String, Value)>,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct JwtVerifyOpts {
+    #[arg(default_value = "-", help = "The token, or - to read it from stdin")]
+    pub token: String,
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        help = "HMAC secret for HS256, or an Ed25519, P-256 or RSA public key"
+    )]
+    pub key: String,
+    #[arg(long, default_value = "HS256", value_parser = parse_jwt_algorithm)]
+    pub alg: JwtAlgorithm,
+    #[arg(long, help = "Require this audience")]
+    pub aud: Option<String>,
+    #[arg(long, help = "Require this issuer")]
+    pub iss: Option<String>,
+    #[arg(
+        long,
+        default_value = "60s",
+        value_parser = parse_duration,
+        help = "Clock skew allowed on exp and nbf"
+    )]
+    pub leeway: Duration,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct JwtDecodeOpts {
+    #[arg(default_value = "-", help = "The token, or - to read it from stdin")]
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    /// HMAC with SHA-256
+    Hs256,
+    /// Ed25519
+    EdDsa,
+    /// ECDSA on P-256 with SHA-256
+    Es256,
+    /// RSASSA-PKCS1-v1_5 with SHA-256
+    Rs256,
+}
+
+fn parse_jwt_algorithm(alg: &str) -> anyhow::Result<JwtAlgorithm> {
+    alg.parse()
+}
+
+impl From<JwtAlgorithm> for &str {
+    fn from(value: JwtAlgorithm) -> Self {
+        match value {
+            JwtAlgorithm::Hs256 => "HS256",
+            JwtAlgorithm::EdDsa => "EdDSA",
+            JwtAlgorithm::Es256 => "ES256",
+            JwtAlgorithm::Rs256 => "RS256",
+        }
+    }
+}
+
+impl FromStr for JwtAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "HS256" => Ok(JwtAlgorithm::Hs256),
+            "EDDSA" => Ok(JwtAlgorithm::EdDsa),
+            "ES256" => Ok(JwtAlgorithm::Es256),
+            "RS256" => Ok(JwtAlgorithm::Rs256),
+            _ => anyhow::bail!("Unsupported JWT algorithm: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for JwtAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+fn parse_duration(duration: &str) -> anyhow::Result<Duration> {
+    Ok(humantime::parse_duration(duration)?)
+}
+
+fn parse_claim(claim: &str) -> anyhow::Result<(String, Value)> {
+    let (key, value) = claim
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("claim must be KEY=VALUE"))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+    Ok((key.into(), value))
+}
+
+fn read_token(token: &str) -> anyhow::Result<String> {
+    if token != "-" {
+        return Ok(token.into());
+    }
+    let mut buf = String::new();
+    get_reader(token)?.read_to_string(&mut buf)?;
+    Ok(buf.trim().into())
+}
+
+impl CmdExecutor for JwtSignOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let claims = JwtClaims {
+            sub: self.sub,
+            aud: self.aud,
+            iss: self.iss,
+            exp: self.exp,
+            nbf: self.nbf,
+            extra: self.claims,
+        };
+        let token = process_jwt_sign(&self.key, self.alg, &(&self.passphrase).into(), &claims)?;
+        println!("{}", token);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for JwtVerifyOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let validation = JwtValidation {
+            aud: self.aud,
+            iss: self.iss,
+            leeway: self.leeway,
+        };
+        let token = process_jwt_verify(
+            &read_token(&self.token)?,
+            &self.key,
+            self.alg,
+            &(&self.passphrase).into(),
+            &validation,
+        )?;
+        println!("{}", serde_json::to_string_pretty(&token)?);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for JwtDecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let token = process_jwt_decode(&read_token(&self.token)?)?;
+        println!("{}", serde_json::to_string_pretty(&token)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_claim() {
+        assert_eq!(
+            parse_claim("admin=true").unwrap(),
+            ("admin".into(), Value::Bool(true))
+        );
+        assert_eq!(
+            parse_claim("role=ops=1").unwrap(),
+            ("role".into(), Value::String("ops=1".into()))
+        );
+        assert!(parse_claim("admin").is_err());
+        assert_eq!(
+            parse_duration("14d").unwrap(),
+            Duration::from_secs(14 * 86400)
+        );
+    }
+}
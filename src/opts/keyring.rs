@@ -0,0 +1,138 @@
+use std::io::Write;
+
+use clap::{Args, Subcommand};
+use enum_dispatch::enum_dispatch;
+
+use crate::{get_secret_writer, get_writer, CmdExecutor, KeyEncoding, Keyring};
+
+use super::{
+    text::{parse_key_encoding, PassphraseOpts},
+    verify_file,
+};
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum KeyringSubCommand {
+    #[command(about = "Add an Ed25519 secret or public key under a name")]
+    Add(KeyringAddOpts),
+    #[command(about = "List the keys in the keyring")]
+    List(KeyringListOpts),
+    #[command(about = "Remove a key from the keyring")]
+    Remove(KeyringRemoveOpts),
+    #[command(about = "Print a public key, or the secret key file, from the keyring")]
+    Export(KeyringExportOpts),
+    #[command(about = "Set the key used when --key is not given")]
+    Default(KeyringDefaultOpts),
+}
+
+#[derive(Debug, Args)]
+pub struct KeyringAddOpts {
+    pub name: String,
+    #[arg(value_parser = verify_file, help = "Ed25519 secret or public key in any supported encoding")]
+    pub key: String,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyringListOpts {
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyringRemoveOpts {
+    #[arg(help = "Key name, fingerprint or key ID")]
+    pub key: String,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyringExportOpts {
+    #[arg(help = "Key name, fingerprint or key ID")]
+    pub key: String,
+    #[arg(
+        long,
+        help = "Export the secret key file as it was added, still encrypted if it was"
+    )]
+    pub secret: bool,
+    #[arg(
+        long,
+        value_parser = parse_key_encoding,
+        default_value = "openssh",
+        conflicts_with = "secret",
+        help = "raw, hex, base64, pem, der, openssh or minisign"
+    )]
+    pub encoding: KeyEncoding,
+    #[arg(short, long, default_value = "-", help = "Output file, - for stdout")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyringDefaultOpts {
+    #[arg(help = "Key name, fingerprint or key ID")]
+    pub key: String,
+}
+
+impl CmdExecutor for KeyringAddOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut keyring = Keyring::open_default()?;
+        let entry = keyring.add(&self.name, &self.key, &(&self.passphrase).into())?;
+        println!("added {} {}", entry.name, entry.fingerprint);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyringListOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let keyring = Keyring::open_default()?;
+        if self.json {
+            let keys: Vec<_> = keyring.list().collect();
+            println!("{}", serde_json::to_string_pretty(&keys)?);
+            return Ok(());
+        }
+        let default = keyring.default_key().map(|entry| entry.name.as_str());
+        for entry in keyring.list() {
+            let marker = if default == Some(entry.name.as_str()) {
+                '*'
+            } else {
+                ' '
+            };
+            let kind = if entry.secret { "sk" } else { "pk" };
+            println!(
+                "{} {} {} {} {}",
+                marker, kind, entry.key_id, entry.fingerprint, entry.name
+            );
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyringRemoveOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let entry = Keyring::open_default()?.remove(&self.key)?;
+        println!("removed {} {}", entry.name, entry.fingerprint);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyringExportOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = Keyring::open_default()?.export(&self.key, self.secret, self.encoding)?;
+        let mut writer = if self.secret {
+            get_secret_writer(&self.output)?
+        } else {
+            get_writer(&self.output)?
+        };
+        writer.write_all(&key)?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyringDefaultOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut keyring = Keyring::open_default()?;
+        let entry = keyring.set_default(&self.key)?;
+        println!("default key {} {}", entry.name, entry.fingerprint);
+        Ok(())
+    }
+}
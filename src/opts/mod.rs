@@ -1,7 +1,10 @@
 mod b64;
 mod csv;
 mod genpass;
+mod hash;
 mod http;
+mod jwt;
+mod keyring;
 mod text;
 
 use std::path::{Path, PathBuf};
@@ -10,11 +13,27 @@ use clap::{Parser, Subcommand};
 use enum_dispatch::enum_dispatch;
 
 pub use self::csv::{CsvOpts, OutputFormat};
-pub use self::text::{TextSignFormat, TextSubCommand};
+pub use self::text::{
+    KeyEncoding, KeyType, ShareEncoding, TextCipher, TextSignFormat, TextSubCommand,
+};
 pub use b64::{Base64Format, Base64SubCommand, DecodeOpts, EncodeOpts};
-pub use genpass::GenPassOpts;
+pub use genpass::{
+    GenPassOpts, GenPassOutputFormat, GenPassSubCommand, PassCheckOpts, TokenOpts, TokenSubCommand,
+    TokenVerifyOpts,
+};
+pub use hash::{HashAlgorithm, HashOpts};
 pub use http::{HttpServeOpts, HttpSubCommand};
-pub use text::{KeyGenerateOpts, TextSignOpts, TextVerifyOpts};
+pub use jwt::{JwtAlgorithm, JwtDecodeOpts, JwtSignOpts, JwtSubCommand, JwtVerifyOpts};
+pub use keyring::{
+    KeyringAddOpts, KeyringDefaultOpts, KeyringExportOpts, KeyringListOpts, KeyringRemoveOpts,
+    KeyringSubCommand,
+};
+pub use text::{
+    BaoDecodeOpts, BaoEncodeOpts, BaoSliceOpts, BaoSubCommand, KeyGenerateOpts, KeyInfoOpts,
+    KeyPubOpts, KeySubCommand, TextCombineOpts, TextDecryptOpts, TextEncryptOpts, TextSignOpts,
+    TextSignTreeOpts, TextSplitOpts, TextSshSignOpts, TextSshVerifyOpts, TextVerifyOpts,
+    TextVerifyTreeOpts, TextX25519Opts,
+};
 
 #[derive(Debug, Parser)]
 #[command(name = "rcli", version, author, about, long_about=None)]
@@ -28,14 +47,28 @@ pub struct Opts {
 pub enum SubCommand {
     #[command(name = "csv", about = "Show CSV, or convert CSV to other formats")]
     Csv(CsvOpts),
-    #[command(name = "genpass", about = "Generate a random password")]
+    #[command(
+        name = "genpass",
+        about = "Generate a random password or check an existing one",
+        args_conflicts_with_subcommands = true
+    )]
     GenPass(GenPassOpts),
     #[command(name = "base64", about = "Base64 encode or decode", subcommand)]
     Base64SubCommand(Base64SubCommand),
-    #[command(name = "text", about = "Sign or verify a message", subcommand)]
+    #[command(
+        name = "text",
+        about = "Sign, verify, encrypt or decrypt a message",
+        subcommand
+    )]
     Text(TextSubCommand),
     #[command(subcommand, about = "Http server")]
     Http(HttpSubCommand),
+    #[command(subcommand, about = "Sign, verify or decode JSON Web Tokens")]
+    Jwt(JwtSubCommand),
+    #[command(about = "Print or check file digests, in sha256sum/b3sum format")]
+    Hash(HashOpts),
+    #[command(subcommand, about = "Manage named keys for text sign and verify")]
+    Keyring(KeyringSubCommand),
 }
 
 fn verify_file(filename: &str) -> Result<String, &'static str> {
@@ -1,12 +1,25 @@
-use std::{fmt, path::PathBuf, str::FromStr};
+use std::{
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::{process_text_generate, process_text_sign, process_text_verify, CmdExecutor};
+use crate::{
+    get_reader, get_secret_writer, get_writer, process_bao_decode, process_bao_encode,
+    process_bao_slice, process_text_cipher_generate, process_text_combine, process_text_decrypt,
+    process_text_encrypt, process_text_generate, process_text_key_info, process_text_key_pub,
+    process_text_sign, process_text_sign_tree, process_text_split, process_text_ssh_sign,
+    process_text_ssh_verify, process_text_verify, process_text_verify_tree, process_text_x25519,
+    resolve_signing_key, resolve_verifying_key, CipherKey, CliError, CmdExecutor, PassphraseSource,
+    SignOptions, SshSigners, TreeFiles, VerifyOptions, BAO_OUTBOARD_EXT,
+};
 
 use enum_dispatch::enum_dispatch;
 use tokio::fs;
 
 use super::{verify_file, verify_path};
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
@@ -15,44 +28,494 @@ pub enum TextSubCommand {
     Sign(TextSignOpts),
     #[command(about = "Verify a message")]
     Verify(TextVerifyOpts),
-    #[command(about = "Generate a random blake3 key or Ed25519 key pair")]
+    #[command(about = "Sign a message into an SSH signature, as ssh-keygen -Y sign does")]
+    SshSign(TextSshSignOpts),
+    #[command(about = "Verify an SSH signature against an allowed_signers file or a key")]
+    SshVerify(TextSshVerifyOpts),
+    #[command(about = "Hash a directory into a signed b3sum manifest")]
+    SignTree(TextSignTreeOpts),
+    #[command(about = "Verify a directory against its signed manifest")]
+    VerifyTree(TextVerifyTreeOpts),
+    #[command(
+        about = "Generate a random blake3 or HMAC key, or an Ed25519, ECDSA or X25519 key pair"
+    )]
     Generate(KeyGenerateOpts),
+    #[command(about = "Encrypt a message with a key file or a passphrase")]
+    Encrypt(TextEncryptOpts),
+    #[command(about = "Decrypt a message")]
+    Decrypt(TextDecryptOpts),
+    #[command(
+        name = "x25519",
+        about = "Convert an Ed25519 key to an age X25519 identity or recipient"
+    )]
+    X25519(TextX25519Opts),
+    #[command(about = "Split a key into Shamir shares, any threshold of which recover it")]
+    Split(TextSplitOpts),
+    #[command(about = "Recover a key from its Shamir shares")]
+    Combine(TextCombineOpts),
+    #[command(subcommand, about = "Inspect an Ed25519 key or derive its public key")]
+    Key(KeySubCommand),
+    #[command(
+        subcommand,
+        about = "Encode a BLAKE3/Bao outboard tree, extract or verify byte ranges against the root hash"
+    )]
+    Bao(BaoSubCommand),
+}
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum KeySubCommand {
+    #[command(about = "Print the algorithm, public key, fingerprint and randomart of a key")]
+    Info(KeyInfoOpts),
+    #[command(name = "pub", about = "Derive the public key of a secret key")]
+    Pub(KeyPubOpts),
+}
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum BaoSubCommand {
+    #[command(about = "Write the outboard tree of a file and print its root hash")]
+    Encode(BaoEncodeOpts),
+    #[command(about = "Verify a byte range against the root hash and write it out")]
+    Decode(BaoDecodeOpts),
+    #[command(about = "Extract the tree nodes and chunks needed to verify a byte range")]
+    Slice(BaoSliceOpts),
 }
 
 #[derive(Debug, Args)]
 pub struct TextSignOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    #[arg(short, long, value_parser = verify_file)]
-    pub key: String,
+    #[arg(
+        short,
+        long,
+        help = "Key file, or a key name, fingerprint or ID in the keyring [default: the keyring default]"
+    )]
+    pub key: Option<String>,
     #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format )]
     pub format: TextSignFormat,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+    #[arg(
+        long,
+        help = "Write a minisign-compatible signature file instead of printing the signature"
+    )]
+    pub sig_file: Option<String>,
+    #[arg(
+        long,
+        requires = "sig_file",
+        help = "Signed comment stored in the signature file, defaults to timestamp and file name"
+    )]
+    pub trusted_comment: Option<String>,
+    #[arg(long, help = "Print the signature as hex, as webhook headers use")]
+    pub hex: bool,
+    #[arg(
+        long,
+        help = "Encode ECDSA signatures as ASN.1 DER instead of raw r || s"
+    )]
+    pub der: bool,
 }
 
 #[derive(Debug, Args)]
 pub struct TextVerifyOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    #[arg(short, long, value_parser = verify_file)]
+    #[arg(
+        short,
+        long,
+        help = "Key file, or a key name, fingerprint or ID in the keyring [default: the key named by the signature file's key ID, or the keyring default]"
+    )]
+    pub key: Option<String>,
+    #[arg(short, long, required_unless_present = "sig_file")]
+    pub sig: Option<String>,
+    #[arg(
+        long,
+        value_parser = verify_file,
+        conflicts_with = "sig",
+        help = "minisign-compatible signature file, it names its own algorithm so --format is ignored"
+    )]
+    pub sig_file: Option<String>,
+    #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format )]
+    pub format: TextSignFormat,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+    #[arg(long, help = "Print the algorithm, key fingerprint and result as JSON")]
+    pub json: bool,
+    #[arg(long, help = "The signature is hex rather than base64")]
+    pub hex: bool,
+    #[arg(
+        long,
+        help = "Accept high-S secp256k1 signatures, as OpenSSL writes them; they are malleable"
+    )]
+    pub allow_high_s: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TextEncryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        help = "32-byte key file, without it the key is derived from a passphrase"
+    )]
+    pub key: Option<String>,
+    #[arg(
+        short,
+        long = "recipient",
+        value_name = "RECIPIENT",
+        conflicts_with = "key",
+        help = "Encrypt to an age1... recipient or Ed25519 public key (or a file with one) as an age file, repeatable"
+    )]
+    pub recipients: Vec<String>,
+    #[arg(long, default_value = "chacha20poly1305", value_parser = parse_text_cipher)]
+    pub cipher: TextCipher,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct TextDecryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        help = "32-byte key file, or for age files an age identity or Ed25519 secret key"
+    )]
+    pub key: Option<String>,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct TextX25519Opts {
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        help = "Ed25519 secret or public key in any supported encoding"
+    )]
     pub key: String,
-    #[arg(short, long)]
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct TextSshSignOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        help = "Ed25519 key file, or a key name, fingerprint or ID in the keyring [default: the keyring default]"
+    )]
+    pub key: Option<String>,
+    #[arg(short, long, help = "What the signature is for, e.g. git or file")]
+    pub namespace: String,
+    #[arg(
+        short,
+        long,
+        default_value = "-",
+        help = "Signature file, - for stdout"
+    )]
+    pub output: String,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct TextSshVerifyOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, value_parser = verify_file, help = "The -----BEGIN SSH SIGNATURE----- file")]
     pub sig: String,
-    #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format )]
+    #[arg(short, long)]
+    pub namespace: String,
+    #[arg(
+        long,
+        value_parser = verify_file,
+        help = "allowed_signers file, as for ssh-keygen -Y verify"
+    )]
+    pub allowed_signers: Option<String>,
+    #[arg(
+        short = 'I',
+        long,
+        requires = "allowed_signers",
+        help = "Require this principal, otherwise any principal the key is allowed for"
+    )]
+    pub principal: Option<String>,
+    #[arg(
+        short,
+        long,
+        conflicts_with = "allowed_signers",
+        help = "Trust only this public key or keyring key [default: the keyring default]"
+    )]
+    pub key: Option<String>,
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TextSplitOpts {
+    #[arg(value_parser = verify_file, help = "Key file to split, its bytes are shared as they are")]
+    pub key: String,
+    #[arg(long, value_parser = clap::value_parser!(u8).range(2..))]
+    pub shares: u8,
+    #[arg(long, value_parser = clap::value_parser!(u8).range(2..))]
+    pub threshold: u8,
+    #[arg(long, value_parser = parse_share_encoding, default_value = "base64")]
+    pub encoding: ShareEncoding,
+    #[arg(
+        short,
+        long,
+        value_parser = verify_path,
+        help = "Write each share to <key>.share<N> in this directory instead of printing them"
+    )]
+    pub output_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct TextCombineOpts {
+    #[arg(
+        value_parser = verify_file,
+        default_value = "-",
+        help = "Files with one share per line, - for stdin"
+    )]
+    pub shares: Vec<String>,
+    #[arg(
+        short,
+        long,
+        default_value = "-",
+        help = "Recovered key file, - for stdout"
+    )]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyInfoOpts {
+    #[arg(
+        value_parser = verify_file,
+        help = "Ed25519 secret or public key; raw, hex and base64 keys are read as secret keys unless named *.pk or *.pub"
+    )]
+    pub key: String,
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyPubOpts {
+    #[arg(value_parser = verify_file, help = "Ed25519 secret key in any supported encoding")]
+    pub key: String,
+    #[arg(
+        long,
+        value_parser = parse_key_encoding,
+        default_value = "openssh",
+        help = "raw, hex, base64, pem, der, openssh or minisign"
+    )]
+    pub encoding: KeyEncoding,
+    #[arg(short, long, default_value = "-", help = "Output file, - for stdout")]
+    pub output: String,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct BaoEncodeOpts {
+    #[arg(value_parser = verify_file)]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        help = "Outboard tree file [default: <input>.obao, required for stdin]"
+    )]
+    pub outboard: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct BaoDecodeOpts {
+    #[arg(
+        value_parser = verify_file,
+        default_value = "-",
+        help = "Content file with --outboard, otherwise a slice from bao slice"
+    )]
+    pub input: String,
+    #[arg(long, value_parser = parse_bao_hash, help = "Root hash printed by bao encode")]
+    pub hash: blake3::Hash,
+    #[arg(long, value_parser = verify_file)]
+    pub outboard: Option<String>,
+    #[arg(long, default_value_t = 0)]
+    pub start: u64,
+    #[arg(long, help = "Bytes to decode [default: to the end]")]
+    pub len: Option<u64>,
+    #[arg(short, long, default_value = "-", help = "Output file, - for stdout")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct BaoSliceOpts {
+    #[arg(value_parser = verify_file)]
+    pub input: String,
+    #[arg(long, help = "Outboard tree file [default: <input>.obao]")]
+    pub outboard: Option<String>,
+    #[arg(long, default_value_t = 0)]
+    pub start: u64,
+    #[arg(long)]
+    pub len: u64,
+    #[arg(short, long, default_value = "-", help = "Output file, - for stdout")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TextSignTreeOpts {
+    #[arg(value_parser = verify_path)]
+    pub dir: PathBuf,
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
+    #[command(flatten)]
+    pub tree: TreeFileOpts,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
 }
 
 #[derive(Debug, Args)]
-pub struct KeyGenerateOpts {
-    #[arg(long, value_parser = parse_text_sign_format, default_value = "blake3")]
+pub struct TextVerifyTreeOpts {
+    #[arg(value_parser = verify_path)]
+    pub dir: PathBuf,
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
+    #[command(flatten)]
+    pub tree: TreeFileOpts,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+    #[arg(long, help = "Print the signature check and the differences as JSON")]
+    pub json: bool,
+}
+
+/// Manifest and signature locations, relative to the directory
+#[derive(Debug, Args)]
+pub struct TreeFileOpts {
+    #[arg(long, help = "Manifest path, defaults to B3SUMS in the directory")]
+    pub manifest: Option<PathBuf>,
+    #[arg(long, help = "Signature path, defaults to the manifest path plus .sig")]
+    pub sig: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyGenerateOpts {
+    #[arg(
+        long,
+        value_parser = parse_key_type,
+        default_value = "blake3",
+        help = "blake3, ed25519, hmac, p256, secp256k1 or x25519; signing format names work too"
+    )]
+    pub format: KeyType,
+    #[arg(
+        long,
+        conflicts_with = "format",
+        help = "Generate a key for text encrypt instead of a signing key"
+    )]
+    pub cipher: bool,
+    #[arg(
+        long,
+        value_parser = parse_key_encoding,
+        default_value = "raw",
+        help = "raw, hex or base64; Ed25519 keys also support pem, der, openssh and minisign, ECDSA keys pem and der; X25519 keys are always written in age format"
+    )]
+    pub encoding: KeyEncoding,
     #[arg(short, long, value_parser = verify_path)]
     pub output_path: PathBuf,
+    #[arg(long, help = "Encrypt the secret key with a passphrase")]
+    pub encrypt: bool,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+}
+
+/// Where the passphrase of an encrypted key comes from, prompts by default
+#[derive(Debug, Args)]
+pub struct PassphraseOpts {
+    #[arg(
+        long,
+        value_name = "VAR",
+        help = "Read the key passphrase from this environment variable"
+    )]
+    pub passphrase_env: Option<String>,
+    #[arg(
+        long,
+        value_name = "FD",
+        conflicts_with = "passphrase_env",
+        help = "Read the key passphrase from this file descriptor"
+    )]
+    pub passphrase_fd: Option<i32>,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum TextSignFormat {
     Blake3,
+    /// pure Ed25519, the message is buffered in memory
     Ed25519,
+    /// Ed25519ph, the message is streamed through SHA-512
+    Ed25519ph,
+    HmacSha256,
+    HmacSha512,
+    HmacSha3_256,
+    /// ECDSA over SHA-256 on NIST P-256
+    P256,
+    /// ECDSA over SHA-256 on secp256k1
+    Secp256k1,
+}
+
+/// Kind of key made by `key generate`
+#[derive(Debug, Clone, Copy)]
+pub enum KeyType {
+    Blake3,
+    /// for both pure Ed25519 and Ed25519ph
+    Ed25519,
+    /// for any of the HMAC formats
+    Hmac,
+    P256,
+    Secp256k1,
+    /// age X25519 keys, for encryption only
+    X25519,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TextCipher {
+    ChaCha20Poly1305,
+    Aes256Gcm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareEncoding {
+    Base64,
+    /// BIP-39 English words, 11 bits each
+    Mnemonic,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum KeyEncoding {
+    Raw,
+    Hex,
+    Base64,
+    /// PKCS#8 / SPKI, as used by OpenSSL
+    Pem,
+    Der,
+    /// OpenSSH private key and `authorized_keys` line
+    Openssh,
+    /// unencrypted minisign secret key and public key
+    Minisign,
 }
 
 fn parse_text_sign_format(format: &str) -> anyhow::Result<TextSignFormat> {
@@ -64,6 +527,12 @@ impl From<TextSignFormat> for &str {
         match value {
             TextSignFormat::Blake3 => "blake3",
             TextSignFormat::Ed25519 => "ed25519",
+            TextSignFormat::Ed25519ph => "ed25519ph",
+            TextSignFormat::HmacSha256 => "hmac-sha256",
+            TextSignFormat::HmacSha512 => "hmac-sha512",
+            TextSignFormat::HmacSha3_256 => "hmac-sha3-256",
+            TextSignFormat::P256 => "p256",
+            TextSignFormat::Secp256k1 => "secp256k1",
         }
     }
 }
@@ -75,6 +544,12 @@ impl FromStr for TextSignFormat {
         match s {
             "blake3" => Ok(TextSignFormat::Blake3),
             "ed25519" => Ok(TextSignFormat::Ed25519),
+            "ed25519ph" => Ok(TextSignFormat::Ed25519ph),
+            "hmac-sha256" => Ok(TextSignFormat::HmacSha256),
+            "hmac-sha512" => Ok(TextSignFormat::HmacSha512),
+            "hmac-sha3-256" => Ok(TextSignFormat::HmacSha3_256),
+            "p256" => Ok(TextSignFormat::P256),
+            "secp256k1" => Ok(TextSignFormat::Secp256k1),
             v => anyhow::bail!("Unsupported format: {}", v),
         }
     }
@@ -86,25 +561,469 @@ impl fmt::Display for TextSignFormat {
     }
 }
 
+fn parse_key_type(key_type: &str) -> anyhow::Result<KeyType> {
+    key_type.parse()
+}
+
+impl From<KeyType> for &str {
+    fn from(value: KeyType) -> Self {
+        match value {
+            KeyType::Blake3 => "blake3",
+            KeyType::Ed25519 => "ed25519",
+            KeyType::Hmac => "hmac",
+            KeyType::P256 => "p256",
+            KeyType::Secp256k1 => "secp256k1",
+            KeyType::X25519 => "x25519",
+        }
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = anyhow::Error;
+
+    /// Also takes the signing format names, the key is the same for all their variants
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "blake3" => Ok(KeyType::Blake3),
+            "ed25519" | "ed25519ph" => Ok(KeyType::Ed25519),
+            "hmac" | "hmac-sha256" | "hmac-sha512" | "hmac-sha3-256" => Ok(KeyType::Hmac),
+            "p256" => Ok(KeyType::P256),
+            "secp256k1" => Ok(KeyType::Secp256k1),
+            "x25519" => Ok(KeyType::X25519),
+            v => anyhow::bail!("Unsupported key type: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+fn parse_text_cipher(cipher: &str) -> anyhow::Result<TextCipher> {
+    cipher.parse()
+}
+
+impl From<TextCipher> for &str {
+    fn from(value: TextCipher) -> Self {
+        match value {
+            TextCipher::ChaCha20Poly1305 => "chacha20poly1305",
+            TextCipher::Aes256Gcm => "aes256gcm",
+        }
+    }
+}
+
+impl FromStr for TextCipher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "chacha20poly1305" => Ok(TextCipher::ChaCha20Poly1305),
+            "aes256gcm" => Ok(TextCipher::Aes256Gcm),
+            v => anyhow::bail!("Unsupported cipher: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for TextCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+pub(crate) fn parse_key_encoding(encoding: &str) -> anyhow::Result<KeyEncoding> {
+    encoding.parse()
+}
+
+impl From<KeyEncoding> for &str {
+    fn from(value: KeyEncoding) -> Self {
+        match value {
+            KeyEncoding::Raw => "raw",
+            KeyEncoding::Hex => "hex",
+            KeyEncoding::Base64 => "base64",
+            KeyEncoding::Pem => "pem",
+            KeyEncoding::Der => "der",
+            KeyEncoding::Openssh => "openssh",
+            KeyEncoding::Minisign => "minisign",
+        }
+    }
+}
+
+impl FromStr for KeyEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "raw" => Ok(KeyEncoding::Raw),
+            "hex" => Ok(KeyEncoding::Hex),
+            "base64" => Ok(KeyEncoding::Base64),
+            "pem" => Ok(KeyEncoding::Pem),
+            "der" => Ok(KeyEncoding::Der),
+            "openssh" => Ok(KeyEncoding::Openssh),
+            "minisign" => Ok(KeyEncoding::Minisign),
+            v => anyhow::bail!("Unsupported key encoding: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for KeyEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+fn parse_bao_hash(hash: &str) -> anyhow::Result<blake3::Hash> {
+    blake3::Hash::from_hex(hash).map_err(|e| anyhow::anyhow!("invalid root hash: {}", e))
+}
+
+fn parse_share_encoding(encoding: &str) -> anyhow::Result<ShareEncoding> {
+    encoding.parse()
+}
+
+impl From<ShareEncoding> for &str {
+    fn from(value: ShareEncoding) -> Self {
+        match value {
+            ShareEncoding::Base64 => "base64",
+            ShareEncoding::Mnemonic => "mnemonic",
+        }
+    }
+}
+
+impl FromStr for ShareEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "base64" => Ok(ShareEncoding::Base64),
+            "mnemonic" | "bip39" => Ok(ShareEncoding::Mnemonic),
+            v => anyhow::bail!("Unsupported share encoding: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for ShareEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<&PassphraseOpts> for PassphraseSource {
+    fn from(opts: &PassphraseOpts) -> Self {
+        match (&opts.passphrase_env, opts.passphrase_fd) {
+            (Some(name), _) => PassphraseSource::Env(name.clone()),
+            (None, Some(fd)) => PassphraseSource::Fd(fd),
+            (None, None) => PassphraseSource::Prompt,
+        }
+    }
+}
+
 impl CmdExecutor for TextSignOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        process_text_sign(&self.input, &self.key, self.format)?;
+        let key = resolve_signing_key(self.key.as_deref(), self.format)?;
+        process_text_sign(
+            &self.input,
+            &key,
+            self.format,
+            &(&self.passphrase).into(),
+            &SignOptions {
+                sig_file: self.sig_file.as_deref(),
+                trusted_comment: self.trusted_comment.as_deref(),
+                hex: self.hex,
+                der: self.der,
+            },
+        )?;
         Ok(())
     }
 }
 
 impl CmdExecutor for TextVerifyOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        process_text_verify(&self.input, &self.key, &self.sig, self.format)?;
+        let key =
+            resolve_verifying_key(self.key.as_deref(), self.sig_file.as_deref(), self.format)?;
+        let report = process_text_verify(
+            &self.input,
+            &key,
+            self.format,
+            &(&self.passphrase).into(),
+            &VerifyOptions {
+                sig: self.sig.as_deref(),
+                sig_file: self.sig_file.as_deref(),
+                hex: self.hex,
+                allow_high_s: self.allow_high_s,
+            },
+        )?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("verify result {}", report.valid);
+            if let (true, Some(comment)) = (report.valid, &report.trusted_comment) {
+                println!("trusted comment: {}", comment);
+            }
+        }
+        if !report.valid {
+            return Err(CliError::VerificationFailed.into());
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextEncryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let passphrase = (&self.passphrase).into();
+        let key = match &self.key {
+            Some(path) => CipherKey::File(path, &passphrase),
+            None if !self.recipients.is_empty() => CipherKey::Recipients(&self.recipients),
+            None => CipherKey::Passphrase(&passphrase),
+        };
+        process_text_encrypt(&self.input, &self.output, self.cipher, key)
+    }
+}
+
+impl CmdExecutor for TextDecryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let passphrase = (&self.passphrase).into();
+        let key = match &self.key {
+            Some(path) => CipherKey::File(path, &passphrase),
+            None => CipherKey::Passphrase(&passphrase),
+        };
+        process_text_decrypt(&self.input, &self.output, key)
+    }
+}
+
+impl CmdExecutor for TextX25519Opts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = process_text_x25519(&self.key, &(&self.passphrase).into())?;
+        print!("{}", key);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextSshSignOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = resolve_signing_key(self.key.as_deref(), TextSignFormat::Ed25519)?;
+        let sig = process_text_ssh_sign(
+            &self.input,
+            &key,
+            &self.namespace,
+            &(&self.passphrase).into(),
+        )?;
+        get_writer(&self.output)?.write_all(sig.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextSshVerifyOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key;
+        let signers = match &self.allowed_signers {
+            Some(path) => SshSigners::AllowedSigners {
+                path,
+                principal: self.principal.as_deref(),
+            },
+            None => {
+                key = resolve_verifying_key(self.key.as_deref(), None, TextSignFormat::Ed25519)?;
+                SshSigners::Key(&key)
+            }
+        };
+        let report = process_text_ssh_verify(&self.input, &self.sig, &self.namespace, signers)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("verify result {}", report.valid);
+            if report.valid && !report.principals.is_empty() {
+                println!("signed by: {}", report.principals.join(", "));
+            }
+            println!("key fingerprint: {}", report.key_fingerprint);
+        }
+        if !report.valid {
+            return Err(CliError::VerificationFailed.into());
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let secret = fs::read(&self.key).await?;
+        let shares = process_text_split(&secret, self.shares, self.threshold, self.encoding)?;
+        let Some(dir) = self.output_dir else {
+            for share in shares {
+                println!("{}", share);
+            }
+            return Ok(());
+        };
+        let name = Path::new(&self.key).file_name().unwrap_or_default();
+        for (i, share) in shares.iter().enumerate() {
+            let path = dir.join(format!("{}.share{}", name.to_string_lossy(), i + 1));
+            let mut file = get_secret_writer(&path.to_string_lossy())?;
+            writeln!(file, "{}", share)?;
+            println!("{}", path.display());
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextCombineOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut shares = vec![];
+        for input in &self.shares {
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut get_reader(input)?, &mut text)?;
+            shares.extend(
+                text.lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(String::from),
+            );
+        }
+        let secret = process_text_combine(&shares)?;
+        get_secret_writer(&self.output)?.write_all(&secret)?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyInfoOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let info = process_text_key_info(&self.key, &(&self.passphrase).into())?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            return Ok(());
+        }
+        let kind = if info.secret { "secret" } else { "public" };
+        println!("algorithm: {} ({} key)", info.algorithm, kind);
+        println!("public key: {}", info.public_key);
+        println!("fingerprint: {}", info.fingerprint);
+        println!("key id: {}", info.key_id);
+        println!("{}", info.randomart);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyPubOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = process_text_key_pub(&self.key, self.encoding, &(&self.passphrase).into())?;
+        get_writer(&self.output)?.write_all(&key)?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for BaoEncodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let outboard = match self.outboard {
+            Some(outboard) => outboard,
+            None if self.input == "-" => anyhow::bail!("--outboard is required for stdin"),
+            None => format!("{}.{}", self.input, BAO_OUTBOARD_EXT),
+        };
+        let (hash, tree) = process_bao_encode(&self.input)?;
+        get_writer(&outboard)?.write_all(&tree)?;
+        println!("{}", hash);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for BaoDecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut writer = get_writer(&self.output)?;
+        process_bao_decode(
+            &self.input,
+            self.outboard.as_deref(),
+            &self.hash,
+            self.start,
+            self.len,
+            &mut writer,
+        )?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for BaoSliceOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let outboard = self
+            .outboard
+            .unwrap_or_else(|| format!("{}.{}", self.input, BAO_OUTBOARD_EXT));
+        let mut writer = get_writer(&self.output)?;
+        process_bao_slice(&self.input, &outboard, self.start, self.len, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl TreeFileOpts {
+    fn files(&self, dir: &Path) -> TreeFiles {
+        TreeFiles::new(dir, self.manifest.as_deref(), self.sig.as_deref())
+    }
+}
+
+impl CmdExecutor for TextSignTreeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let files = self.tree.files(&self.dir);
+        let count = process_text_sign_tree(
+            &self.dir,
+            &files,
+            &self.key,
+            self.format,
+            &(&self.passphrase).into(),
+        )?;
+        println!("signed {} files into {}", count, files.manifest.display());
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextVerifyTreeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let files = self.tree.files(&self.dir);
+        let report = process_text_verify_tree(
+            &self.dir,
+            &files,
+            &self.key,
+            self.format,
+            &(&self.passphrase).into(),
+        )?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else if !report.signature.valid {
+            println!("manifest signature invalid");
+        } else {
+            for (kind, paths) in [
+                ("missing", &report.missing),
+                ("extra", &report.extra),
+                ("modified", &report.modified),
+            ] {
+                for path in paths {
+                    println!("{}: {}", kind, path);
+                }
+            }
+            println!("verify result {}", report.is_valid());
+        }
+        if !report.is_valid() {
+            return Err(CliError::VerificationFailed.into());
+        }
         Ok(())
     }
 }
 
 impl CmdExecutor for KeyGenerateOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let keys = process_text_generate(self.format)?;
+        // naming a passphrase source implies --encrypt
+        let encrypt = self.encrypt
+            || self.passphrase.passphrase_env.is_some()
+            || self.passphrase.passphrase_fd.is_some();
+        let passphrase = encrypt.then(|| (&self.passphrase).into());
+        let keys = if self.cipher {
+            process_text_cipher_generate(self.encoding, passphrase.as_ref())?
+        } else {
+            process_text_generate(self.format, self.encoding, passphrase.as_ref())?
+        };
         for (k, v) in keys {
-            fs::write(self.output_path.join(k), v).await?
+            let path = self.output_path.join(k);
+            // ssh-keygen refuses private keys readable by others
+            let mut file = if k.ends_with(".pk") {
+                get_writer(&path.to_string_lossy())?
+            } else {
+                get_secret_writer(&path.to_string_lossy())?
+            };
+            file.write_all(&v)?;
         }
         Ok(())
     }
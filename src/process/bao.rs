@@ -0,0 +1,174 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+};
+
+use ::bao::{
+    decode::{Decoder, SliceDecoder},
+    encode::{Encoder, SliceExtractor},
+};
+use blake3::Hash;
+
+use crate::{get_reader, CliError};
+
+/// Extension of the outboard tree written next to the content
+pub const BAO_OUTBOARD_EXT: &str = "obao";
+
+/// Hash the input and return the root hash and the outboard tree: an 8-byte
+/// little-endian length, then the parent nodes of the BLAKE3 tree in pre-order.
+/// The root hash is the BLAKE3 hash of the input
+pub fn process_bao_encode(input: &str) -> anyhow::Result<(Hash, Vec<u8>)> {
+    let mut encoder = Encoder::new_outboard(Cursor::new(vec![]));
+    io::copy(&mut get_reader(input)?, &mut encoder)?;
+    let hash = encoder.finalize()?;
+    Ok((hash, encoder.into_inner().into_inner()))
+}
+
+/// Extract the part of the tree and the chunks needed to verify `start..start + len`,
+/// a slice always holds at least one chunk, the last one when `start` is past the end
+pub fn process_bao_slice(
+    input: &str,
+    outboard: &str,
+    start: u64,
+    len: u64,
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let (content, outboard) = (File::open(input)?, File::open(outboard)?);
+    let mut extractor = SliceExtractor::new_outboard(content, outboard, start, len);
+    io::copy(&mut extractor, writer)?;
+    Ok(())
+}
+
+/// Verify `start..start + len` against the root hash and write it out, chunk by chunk
+/// as each one is verified. Reads the content and its outboard tree, or without
+/// an outboard a slice made for the same range
+pub fn process_bao_decode(
+    input: &str,
+    outboard: Option<&str>,
+    hash: &Hash,
+    start: u64,
+    len: Option<u64>,
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let len = len.unwrap_or(u64::MAX);
+    let copied = match outboard {
+        Some(outboard) => {
+            let (content, outboard) = (File::open(input)?, File::open(outboard)?);
+            let mut decoder = Decoder::new_outboard(content, outboard, hash);
+            decoder.seek(SeekFrom::Start(start)).map_err(verify_error)?;
+            io::copy(&mut decoder.take(len), writer)
+        }
+        None => {
+            let mut decoder = SliceDecoder::new(get_reader(input)?, hash, start, len);
+            io::copy(&mut decoder, writer)
+        }
+    };
+    copied.map_err(verify_error)?;
+    Ok(())
+}
+
+/// bao reports a hash mismatch as invalid data and a short slice or outboard as EOF
+fn verify_error(e: io::Error) -> anyhow::Error {
+    match e.kind() {
+        io::ErrorKind::InvalidData => anyhow::Error::from(CliError::VerificationFailed)
+            .context("the content does not match the root hash"),
+        io::ErrorKind::UnexpectedEof => anyhow::Error::from(e)
+            .context("the bao slice or outboard is truncated, or was made for another range"),
+        _ => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [usize; 8] = [0, 1, 1024, 1025, 2048, 3073, 5000, 65536 + 7];
+
+    fn content(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Content and its outboard written to a temporary directory
+    fn encoded(len: usize) -> (tempfile::TempDir, String, String, Hash) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("data.bin");
+        std::fs::write(&input, content(len)).unwrap();
+        let input = input.to_string_lossy().to_string();
+        let (hash, tree) = process_bao_encode(&input).unwrap();
+        let outboard = format!("{}.{}", input, BAO_OUTBOARD_EXT);
+        std::fs::write(&outboard, tree).unwrap();
+        (dir, input, outboard, hash)
+    }
+
+    fn decode(
+        input: &str,
+        outboard: Option<&str>,
+        hash: &Hash,
+        start: u64,
+        len: u64,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut out = vec![];
+        process_bao_decode(input, outboard, hash, start, Some(len), &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_bao_encode() {
+        for len in SIZES {
+            let (_dir, input, outboard, hash) = encoded(len);
+            assert_eq!(hash, blake3::hash(&content(len)));
+            // the header, then 64 bytes for each of the chunks - 1 parents
+            let parents = len.div_ceil(1024).max(1) - 1;
+            assert_eq!(
+                std::fs::metadata(&outboard).unwrap().len() as usize,
+                8 + parents * 64
+            );
+            let decoded = decode(&input, Some(&outboard), &hash, 0, u64::MAX).unwrap();
+            assert_eq!(decoded, content(len));
+        }
+    }
+
+    #[test]
+    fn test_bao_slice() {
+        let (dir, input, outboard, hash) = encoded(65536 + 7);
+        let data = content(65536 + 7);
+        let slice = dir.path().join("slice").to_string_lossy().to_string();
+        for (start, len) in [(0, 1), (1000, 100), (4096, 9000), (65530, 100)] {
+            let mut out = vec![];
+            process_bao_slice(&input, &outboard, start, len, &mut out).unwrap();
+            assert!(out.len() < data.len());
+            std::fs::write(&slice, out).unwrap();
+            let end = (start + len).min(data.len() as u64) as usize;
+            let expected = &data[start as usize..end];
+            assert_eq!(decode(&slice, None, &hash, start, len).unwrap(), expected);
+            assert_eq!(
+                decode(&input, Some(&outboard), &hash, start, len).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_bao_rejects_tampering() {
+        let (dir, input, outboard, hash) = encoded(5000);
+        let mut slice = vec![];
+        process_bao_slice(&input, &outboard, 2048, 100, &mut slice).unwrap();
+        let last = slice.len() - 1;
+        slice[last] ^= 1;
+        let slice_file = dir.path().join("slice").to_string_lossy().to_string();
+        std::fs::write(&slice_file, &slice).unwrap();
+        let err = decode(&slice_file, None, &hash, 2048, 100).unwrap_err();
+        assert!(err.downcast_ref::<CliError>().is_some());
+        std::fs::write(&slice_file, &slice[..slice.len() - 10]).unwrap();
+        assert!(decode(&slice_file, None, &hash, 2048, 100).is_err());
+
+        // the untouched range still verifies, only the modified chunk fails
+        let mut data = content(5000);
+        data[4999] ^= 1;
+        std::fs::write(&input, data).unwrap();
+        assert!(decode(&input, Some(&outboard), &hash, 0, 1024).is_ok());
+        assert!(decode(&input, Some(&outboard), &hash, 0, u64::MAX).is_err());
+        let other = blake3::hash(b"x");
+        assert!(decode(&input, Some(&outboard), &other, 0, 1).is_err());
+    }
+}
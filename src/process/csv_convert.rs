@@ -4,6 +4,7 @@ use std::{collections::HashMap, fs};
 
 use crate::opts::OutputFormat;
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct Player {
@@ -0,0 +1,405 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use aes_gcm::Aes256Gcm;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, read::DecoderReader, write::EncoderWriter};
+use chacha20poly1305::{
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        AeadCore, AeadInPlace, KeyInit, Payload,
+    },
+    consts::U12,
+    ChaCha20Poly1305,
+};
+use rand::{rngs::OsRng, RngCore};
+
+use super::{
+    key_crypt::{derive_key, read_key, KdfParams, KDF_PARAMS},
+    key_format::{decode_key, encode_key},
+    text::{encrypt_secret_keys, TextKeyGenerator},
+    x25519::{age_decrypt, age_encrypt, load_identities, load_recipient},
+};
+use crate::{get_reader, get_writer, CliError, KeyEncoding, PassphraseSource, TextCipher};
+
+const MAGIC: &[u8] = b"rcli-enc";
+const VERSION: u8 = 1;
+const KDF_NONE: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+const SALT_LEN: usize = 16;
+/// 96-bit nonce minus the 32-bit chunk counter and the last-chunk flag of STREAM
+const NONCE_PREFIX_LEN: usize = 7;
+const TAG_LEN: usize = 16;
+/// plaintext bytes per chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Where the encryption key comes from
+pub enum CipherKey<'a> {
+    /// a key file, itself possibly passphrase-encrypted
+    File(&'a str, &'a PassphraseSource),
+    /// derived from a passphrase with Argon2id
+    Passphrase(&'a PassphraseSource),
+    /// age X25519 recipients or Ed25519 public keys, written as an age file
+    Recipients(&'a [String]),
+}
+
+/// A random 256-bit key, both ciphers use the same key size
+struct SymmetricKey;
+
+impl TextKeyGenerator for SymmetricKey {
+    fn generate(encoding: KeyEncoding) -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        let mut map = HashMap::new();
+        map.insert("cipher.txt", encode_key(&key, encoding)?);
+        Ok(map)
+    }
+}
+
+/// Generate a key for `text encrypt`, optionally locked with a passphrase
+pub fn process_text_cipher_generate(
+    encoding: KeyEncoding,
+    passphrase: Option<&PassphraseSource>,
+) -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
+    let mut keys = SymmetricKey::generate(encoding)?;
+    if let Some(passphrase) = passphrase {
+        encrypt_secret_keys(&mut keys, passphrase)?;
+    }
+    Ok(keys)
+}
+
+/// Encrypt the input in 64 KiB STREAM chunks and print it as a single base64 line.
+/// The header names the cipher and, for passphrases, the Argon2id salt and costs.
+/// Messages to recipients are armored age files instead
+pub fn process_text_encrypt(
+    input: &str,
+    output: &str,
+    cipher: TextCipher,
+    key: CipherKey,
+) -> anyhow::Result<()> {
+    let mut reader = get_reader(input)?;
+    if let CipherKey::Recipients(recipients) = key {
+        let recipients = recipients
+            .iter()
+            .map(|r| load_recipient(r))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut writer = get_writer(output)?;
+        age_encrypt(&mut reader, &mut writer, &recipients)?;
+        writer.flush()?;
+        return Ok(());
+    }
+    let mut writer = EncoderWriter::new(get_writer(output)?, &URL_SAFE_NO_PAD);
+    encrypt(&mut reader, &mut writer, cipher, key, KDF_PARAMS)?;
+    let mut writer = writer.finish()?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Decrypt the output of [`process_text_encrypt`], the cipher is read from the header.
+/// age files are decrypted with the identity or Ed25519 secret key given as the key
+pub fn process_text_decrypt(input: &str, output: &str, key: CipherKey) -> anyhow::Result<()> {
+    let mut reader = get_reader(input)?;
+    let mut prefix = Vec::new();
+    (&mut reader).take(64).read_to_end(&mut prefix)?;
+    let is_age = is_age_file(&prefix);
+    let mut reader = io::Cursor::new(prefix).chain(reader);
+    let mut writer = get_writer(output)?;
+    if is_age {
+        let CipherKey::File(path, passphrase) = key else {
+            anyhow::bail!("the message is an age file, pass an age identity or Ed25519 key");
+        };
+        let identities = load_identities(path, passphrase)?;
+        age_decrypt(&mut reader, &mut writer, &identities)?;
+        writer.flush()?;
+        return Ok(());
+    }
+    let mut reader = DecoderReader::new(SkipWhitespace(reader), &URL_SAFE_NO_PAD);
+    decrypt(&mut reader, &mut writer, key)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn encrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    cipher: TextCipher,
+    key: CipherKey,
+    params: KdfParams,
+) -> anyhow::Result<()> {
+    let mut nonce = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = MAGIC.to_vec();
+    header.push(VERSION);
+    header.push(cipher_id(cipher));
+    let key = match key {
+        CipherKey::File(path, passphrase) => {
+            header.push(KDF_NONE);
+            load_key(path, passphrase)?
+        }
+        CipherKey::Passphrase(passphrase) => {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            header.push(KDF_ARGON2ID);
+            header.extend_from_slice(&params.to_bytes());
+            header.extend_from_slice(&salt);
+            derive_key(&passphrase.read_new()?, &salt, params)?
+        }
+        CipherKey::Recipients(_) => anyhow::bail!("messages to recipients are age files"),
+    };
+    header.extend_from_slice(&nonce);
+    writer.write_all(&header)?;
+
+    // the header is authenticated as associated data of every chunk
+    match cipher {
+        TextCipher::ChaCha20Poly1305 => {
+            let aead = ChaCha20Poly1305::new(&key.into());
+            encrypt_chunks(aead, &nonce, &header, reader, writer)
+        }
+        TextCipher::Aes256Gcm => {
+            let aead = Aes256Gcm::new(&key.into());
+            encrypt_chunks(aead, &nonce, &header, reader, writer)
+        }
+    }
+}
+
+fn decrypt(reader: &mut dyn Read, writer: &mut dyn Write, key: CipherKey) -> anyhow::Result<()> {
+    let mut header = vec![0u8; MAGIC.len() + 3];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| anyhow::anyhow!("input is not an rcli encrypted message"))?;
+    if !header.starts_with(MAGIC) {
+        anyhow::bail!("input is not an rcli encrypted message");
+    }
+    let (version, cipher, kdf) = (header[8], header[9], header[10]);
+    if version != VERSION {
+        anyhow::bail!("unsupported encrypted message version: {}", version);
+    }
+    let cipher = match cipher {
+        1 => TextCipher::ChaCha20Poly1305,
+        2 => TextCipher::Aes256Gcm,
+        v => anyhow::bail!("unsupported cipher id: {}", v),
+    };
+
+    let key = match (kdf, key) {
+        (KDF_NONE, CipherKey::File(path, passphrase)) => load_key(path, passphrase)?,
+        (KDF_ARGON2ID, CipherKey::Passphrase(passphrase)) => {
+            let mut kdf = [0u8; KdfParams::LEN + SALT_LEN];
+            reader.read_exact(&mut kdf)?;
+            header.extend_from_slice(&kdf);
+            let params = KdfParams::from_bytes(&kdf[..KdfParams::LEN])?;
+            derive_key(
+                &passphrase.read("Passphrase: ")?,
+                &kdf[KdfParams::LEN..],
+                params,
+            )?
+        }
+        (KDF_NONE, _) => anyhow::bail!("the message was encrypted with a key file, pass --key"),
+        (KDF_ARGON2ID, _) => {
+            anyhow::bail!("the message was encrypted with a passphrase, drop --key")
+        }
+        (v, _) => anyhow::bail!("unsupported key derivation id: {}", v),
+    };
+
+    let mut nonce = [0u8; NONCE_PREFIX_LEN];
+    reader.read_exact(&mut nonce)?;
+    header.extend_from_slice(&nonce);
+
+    match cipher {
+        TextCipher::ChaCha20Poly1305 => {
+            let aead = ChaCha20Poly1305::new(&key.into());
+            decrypt_chunks(aead, &nonce, &header, reader, writer)
+        }
+        TextCipher::Aes256Gcm => {
+            let aead = Aes256Gcm::new(&key.into());
+            decrypt_chunks(aead, &nonce, &header, reader, writer)
+        }
+    }
+}
+
+fn encrypt_chunks<A: AeadInPlace + KeyInit + AeadCore<NonceSize = U12>>(
+    aead: A,
+    nonce: &[u8; NONCE_PREFIX_LEN],
+    aad: &[u8],
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let mut stream = EncryptorBE32::from_aead(aead, nonce.as_ref().into());
+    let failed = |_| anyhow::anyhow!("encryption failed");
+    // read one chunk ahead so the last chunk can be flagged as such
+    let mut chunk = read_chunk(reader, CHUNK_SIZE)?;
+    loop {
+        let next = read_chunk(reader, CHUNK_SIZE)?;
+        let msg = Payload { msg: &chunk, aad };
+        if next.is_empty() {
+            writer.write_all(&stream.encrypt_last(msg).map_err(failed)?)?;
+            return Ok(());
+        }
+        writer.write_all(&stream.encrypt_next(msg).map_err(failed)?)?;
+        chunk = next;
+    }
+}
+
+fn decrypt_chunks<A: AeadInPlace + KeyInit + AeadCore<NonceSize = U12>>(
+    aead: A,
+    nonce: &[u8; NONCE_PREFIX_LEN],
+    aad: &[u8],
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let mut stream = DecryptorBE32::from_aead(aead, nonce.as_ref().into());
+    // a truncated message fails too, its last chunk was not sealed as the last one
+    let failed = |_| {
+        anyhow::Error::from(CliError::VerificationFailed)
+            .context("decryption failed: wrong key or passphrase, or the message was modified")
+    };
+    let mut chunk = read_chunk(reader, CHUNK_SIZE + TAG_LEN)?;
+    loop {
+        let next = read_chunk(reader, CHUNK_SIZE + TAG_LEN)?;
+        let msg = Payload { msg: &chunk, aad };
+        if next.is_empty() {
+            writer.write_all(&stream.decrypt_last(msg).map_err(failed)?)?;
+            return Ok(());
+        }
+        writer.write_all(&stream.decrypt_next(msg).map_err(failed)?)?;
+        chunk = next;
+    }
+}
+
+/// Read up to `size` bytes, short only at the end of the input
+fn read_chunk(reader: &mut dyn Read, size: usize) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+fn load_key(path: &str, passphrase: &PassphraseSource) -> anyhow::Result<[u8; 32]> {
+    let key = read_key(path, passphrase)?;
+    Ok(decode_key(&key).map_err(CliError::InvalidKey)?)
+}
+
+fn is_age_file(prefix: &[u8]) -> bool {
+    prefix.starts_with(b"age-encryption.org/")
+        || prefix
+            .trim_ascii_start()
+            .starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+}
+
+fn cipher_id(cipher: TextCipher) -> u8 {
+    match cipher {
+        TextCipher::ChaCha20Poly1305 => 1,
+        TextCipher::Aes256Gcm => 2,
+    }
+}
+
+/// Drops the line breaks around the base64 text
+struct SkipWhitespace<R>(R);
+
+impl<R: Read> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.0.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut len = 0;
+            for i in 0..n {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[len] = buf[i];
+                    len += 1;
+                }
+            }
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PARAMS: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    fn round_trip(data: &[u8], cipher: TextCipher, key: CipherKey, key2: CipherKey) -> Vec<u8> {
+        let mut encrypted = Vec::new();
+        encrypt(&mut &data[..], &mut encrypted, cipher, key, TEST_PARAMS).unwrap();
+        assert_eq!(
+            encrypted.len(),
+            data.len() + data.len().div_ceil(CHUNK_SIZE).max(1) * TAG_LEN + header_len(&encrypted)
+        );
+        let mut decrypted = Vec::new();
+        decrypt(&mut &encrypted[..], &mut decrypted, key2).unwrap();
+        assert_eq!(decrypted, data);
+        encrypted
+    }
+
+    fn header_len(encrypted: &[u8]) -> usize {
+        let kdf = if encrypted[10] == KDF_ARGON2ID {
+            KdfParams::LEN + SALT_LEN
+        } else {
+            0
+        };
+        MAGIC.len() + 3 + kdf + NONCE_PREFIX_LEN
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_key_file() {
+        let passphrase = PassphraseSource::default();
+        let key = || CipherKey::File("fixtures/blake3.txt", &passphrase);
+        for cipher in [TextCipher::ChaCha20Poly1305, TextCipher::Aes256Gcm] {
+            for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE * 2 + 7] {
+                let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+                round_trip(&data, cipher, key(), key());
+            }
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_passphrase() {
+        std::env::set_var("RCLI_TEST_CIPHER_PASSPHRASE", "open sesame");
+        let passphrase = PassphraseSource::Env("RCLI_TEST_CIPHER_PASSPHRASE".into());
+        let key = || CipherKey::Passphrase(&passphrase);
+        let encrypted = round_trip(b"hello world!", TextCipher::Aes256Gcm, key(), key());
+
+        // a key file cannot open a passphrase message
+        let default = PassphraseSource::default();
+        let file = CipherKey::File("fixtures/blake3.txt", &default);
+        assert!(decrypt(&mut &encrypted[..], &mut Vec::new(), file).is_err());
+
+        // a crafted header asking for 4 TiB is refused before argon2 runs
+        let mut crafted = encrypted.clone();
+        let m_cost = MAGIC.len() + 3;
+        crafted[m_cost..m_cost + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = decrypt(&mut &crafted[..], &mut Vec::new(), key()).unwrap_err();
+        assert!(err.to_string().contains("exceed the limit"));
+    }
+
+    #[test]
+    fn test_decrypt_detects_tampering() {
+        let passphrase = PassphraseSource::default();
+        let key = || CipherKey::File("fixtures/blake3.txt", &passphrase);
+        let data = vec![7u8; CHUNK_SIZE + 100];
+        let encrypted = round_trip(&data, TextCipher::ChaCha20Poly1305, key(), key());
+
+        let mut flipped = encrypted.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(decrypt(&mut &flipped[..], &mut Vec::new(), key()).is_err());
+
+        // drop the last chunk, the first one was not sealed as the last
+        let truncated = &encrypted[..header_len(&encrypted) + CHUNK_SIZE + TAG_LEN];
+        let err = decrypt(&mut &truncated[..], &mut Vec::new(), key()).unwrap_err();
+        assert_eq!(crate::exit_code(&err), crate::EXIT_VERIFY_FAILED);
+
+        // the header is authenticated, switching the cipher id fails
+        let mut header = encrypted.clone();
+        header[9] = 2;
+        assert!(decrypt(&mut &header[..], &mut Vec::new(), key()).is_err());
+    }
+}
@@ -1,50 +1,374 @@
-use rand::seq::SliceRandom;
+use rand::{rngs::OsRng, seq::SliceRandom};
+use serde::Serialize;
+use zxcvbn::zxcvbn;
 
-const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
-const NUMBER: &[u8] = b"123456789";
+use crate::opts::GenPassOutputFormat;
+
+use super::PwnedPasswords;
+
+const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const NUMBER: &[u8] = b"0123456789";
 const SYMBOL: &[u8] = b"!@#$%^&*()_-=[]{}:,.<>?";
+/// look-alike characters, left out unless explicitly allowed
+const AMBIGUOUS: &[u8] = b"0OlI";
+const VOWELS: &[u8] = b"aeiouAEIOU";
 
-pub fn process_genpass(
-    length: u8,
-    upper: bool,
-    lower: bool,
-    number: bool,
-    symbol: bool,
-) -> anyhow::Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password: Vec<u8> = Vec::new();
-    let mut chars = Vec::new();
+pub const MAX_PASSWORD_LENGTH: usize = 4096;
+/// how many times a breached password is regenerated before giving up
+const MAX_PWNED_RETRIES: usize = 16;
+/// zxcvbn gets slow on long inputs, only this many leading chars are analyzed
+const MAX_ESTIMATE_LENGTH: usize = 256;
 
-    if upper {
-        chars.extend_from_slice(UPPER);
-        if let Some(c) = UPPER.choose(&mut rng) {
-            password.push(*c);
+#[derive(Debug, Clone, Default)]
+pub enum PasswordMode {
+    /// characters picked uniformly from the enabled classes
+    #[default]
+    Random,
+    /// alternating consonants and vowels, e.g. `tobaruke`
+    Pronounceable,
+    /// one character per template position:
+    /// `C`/`c` upper/lower consonant, `V`/`v` upper/lower vowel,
+    /// `A` uppercase, `a` lowercase, `9` number, `#` symbol, `*` any class,
+    /// `\x` the literal `x`, anything else is copied as is
+    Pattern(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub mode: PasswordMode,
+    /// ignored in pattern mode, where the template decides the length
+    pub length: usize,
+    pub upper: bool,
+    pub lower: bool,
+    pub number: bool,
+    pub symbol: bool,
+    /// minimum count per class, `None` means one char for an enabled class
+    /// (or zero when a custom charset is used)
+    pub min_upper: Option<usize>,
+    pub min_lower: Option<usize>,
+    pub min_numbers: Option<usize>,
+    pub min_symbols: Option<usize>,
+    /// characters that must never appear in the password
+    pub exclude: String,
+    /// fully custom alphabet, replaces the built-in class tables
+    pub charset: Option<String>,
+    pub allow_ambiguous: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            mode: PasswordMode::Random,
+            length: 16,
+            upper: true,
+            lower: true,
+            number: true,
+            symbol: true,
+            min_upper: None,
+            min_lower: None,
+            min_numbers: None,
+            min_symbols: None,
+            exclude: String::new(),
+            charset: None,
+            allow_ambiguous: false,
         }
     }
+}
 
-    if lower {
-        chars.extend_from_slice(LOWER);
-        if let Some(c) = LOWER.choose(&mut rng) {
-            password.push(*c);
+/// A class of characters the password must contain at least `min` of
+struct CharClass {
+    chars: Vec<u8>,
+    min: usize,
+}
+
+impl PasswordPolicy {
+    /// Resolve the policy into the full alphabet and the per-class requirements,
+    /// rejecting combinations that can never be satisfied
+    fn resolve(&self) -> anyhow::Result<(Vec<u8>, Vec<CharClass>)> {
+        check_length(self.length)?;
+
+        let exclude = self.exclude.as_bytes();
+        let classes = [
+            ("uppercase", "upper", UPPER, self.upper, self.min_upper),
+            ("lowercase", "lower", LOWER, self.lower, self.min_lower),
+            ("number", "numbers", NUMBER, self.number, self.min_numbers),
+            ("symbol", "symbols", SYMBOL, self.symbol, self.min_symbols),
+        ];
+
+        let custom = match &self.charset {
+            Some(charset) => {
+                if let Some(c) = charset.chars().find(|c| !c.is_ascii_graphic()) {
+                    anyhow::bail!("charset must be printable ASCII, found {:?}", c);
+                }
+                let mut chars = charset.as_bytes().to_vec();
+                chars.sort_unstable();
+                chars.dedup();
+                // a disabled class removes its characters from the custom charset as well
+                chars.retain(|c| {
+                    !exclude.contains(c)
+                        && classes
+                            .iter()
+                            .all(|(_, _, table, enabled, _)| *enabled || !table.contains(c))
+                });
+                Some(chars)
+            }
+            None => None,
+        };
+
+        let mut alphabet = Vec::new();
+        let mut required = Vec::new();
+        for (name, flag, table, enabled, min) in classes {
+            if !enabled {
+                if min.unwrap_or(0) > 0 {
+                    anyhow::bail!("--min-{} requires {} characters to be enabled", flag, name);
+                }
+                continue;
+            }
+
+            let chars: Vec<u8> = match &custom {
+                Some(custom) => table
+                    .iter()
+                    .filter(|c| custom.contains(c))
+                    .copied()
+                    .collect(),
+                None => table
+                    .iter()
+                    .filter(|c| self.allow_ambiguous || !AMBIGUOUS.contains(c))
+                    .filter(|c| !exclude.contains(c))
+                    .copied()
+                    .collect(),
+            };
+            let min = min.unwrap_or(if custom.is_some() { 0 } else { 1 });
+
+            if chars.is_empty() {
+                match custom {
+                    Some(_) if min > 0 => anyhow::bail!(
+                        "charset has no {} characters to satisfy --min-{}",
+                        name,
+                        flag
+                    ),
+                    Some(_) => continue,
+                    None => anyhow::bail!("all {} characters are excluded", name),
+                }
+            }
+
+            if custom.is_none() {
+                alphabet.extend_from_slice(&chars);
+            }
+            if min > 0 {
+                required.push(CharClass { chars, min });
+            }
         }
+
+        let alphabet = custom.unwrap_or(alphabet);
+        if alphabet.is_empty() {
+            anyhow::bail!("no characters left to generate a password from");
+        }
+
+        let total: usize = required.iter().map(|class| class.min).sum();
+        if total > self.length {
+            anyhow::bail!(
+                "password length {} is too short for the {} required characters",
+                self.length,
+                total
+            );
+        }
+
+        Ok((alphabet, required))
     }
 
-    if number {
-        chars.extend_from_slice(NUMBER);
-        if let Some(c) = NUMBER.choose(&mut rng) {
-            password.push(*c);
+    /// Resolve the pronounceable and pattern modes into the characters allowed
+    /// at each position of the password
+    fn positions(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        if self.charset.is_some()
+            || self.min_upper.is_some()
+            || self.min_lower.is_some()
+            || self.min_numbers.is_some()
+            || self.min_symbols.is_some()
+        {
+            anyhow::bail!("--charset and --min-* only apply to random passwords");
         }
+        if !(self.upper && self.lower && self.number && self.symbol) {
+            anyhow::bail!(
+                "turning off --uppercase, --lowercase, --number or --symbol only applies to random passwords"
+            );
+        }
+
+        let consonants = |table: &[u8]| -> Vec<u8> {
+            table
+                .iter()
+                .filter(|c| !VOWELS.contains(c))
+                .copied()
+                .collect()
+        };
+        let vowels = |table: &[u8]| -> Vec<u8> {
+            table
+                .iter()
+                .filter(|c| VOWELS.contains(c))
+                .copied()
+                .collect()
+        };
+
+        let positions: Vec<Vec<u8>> = match &self.mode {
+            PasswordMode::Random => anyhow::bail!("random passwords have no fixed positions"),
+            PasswordMode::Pronounceable => {
+                check_length(self.length)?;
+                let consonants = self.pool(&consonants(LOWER));
+                let vowels = self.pool(&vowels(LOWER));
+                (0..self.length)
+                    .map(|i| if i % 2 == 0 { &consonants } else { &vowels }.clone())
+                    .collect()
+            }
+            PasswordMode::Pattern(pattern) => {
+                let mut positions = Vec::new();
+                let mut chars = pattern.chars();
+                while let Some(c) = chars.next() {
+                    let pool = match c {
+                        'C' => self.pool(&consonants(UPPER)),
+                        'c' => self.pool(&consonants(LOWER)),
+                        'V' => self.pool(&vowels(UPPER)),
+                        'v' => self.pool(&vowels(LOWER)),
+                        'A' => self.pool(UPPER),
+                        'a' => self.pool(LOWER),
+                        '9' => self.pool(NUMBER),
+                        '#' => self.pool(SYMBOL),
+                        '*' => self.pool(&[UPPER, LOWER, NUMBER, SYMBOL].concat()),
+                        '\\' => match chars.next() {
+                            Some(c) => literal(c)?,
+                            None => anyhow::bail!("pattern ends with a dangling escape"),
+                        },
+                        c => literal(c)?,
+                    };
+                    if pool.is_empty() {
+                        anyhow::bail!("all characters for pattern class '{}' are excluded", c);
+                    }
+                    positions.push(pool);
+                }
+                check_length(positions.len())?;
+                positions
+            }
+        };
+
+        if positions.iter().any(|pool| pool.is_empty()) {
+            anyhow::bail!("no characters left to generate a password from");
+        }
+        Ok(positions)
     }
 
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        if let Some(c) = SYMBOL.choose(&mut rng) {
-            password.push(*c);
+    /// Filter a class table by the ambiguous and excluded characters
+    fn pool(&self, table: &[u8]) -> Vec<u8> {
+        table
+            .iter()
+            .filter(|c| self.allow_ambiguous || !AMBIGUOUS.contains(c))
+            .filter(|c| !self.exclude.as_bytes().contains(c))
+            .copied()
+            .collect()
+    }
+
+    /// Entropy of the password in bits. Pronounceable and pattern passwords
+    /// are counted position by position, so literals add nothing.
+    /// For random ones it is a lower bound: the required characters are counted
+    /// against their own class only, the rest against the whole alphabet
+    pub fn entropy_bits(&self) -> anyhow::Result<f64> {
+        if !matches!(self.mode, PasswordMode::Random) {
+            let positions = self.positions()?;
+            return Ok(positions
+                .iter()
+                .map(|pool| (pool.len() as f64).log2())
+                .sum());
+        }
+
+        let (alphabet, required) = self.resolve()?;
+        let mut bits = 0.0;
+        let mut remaining = self.length;
+        for class in &required {
+            bits += class.min as f64 * (class.chars.len() as f64).log2();
+            remaining -= class.min;
         }
+        bits += remaining as f64 * (alphabet.len() as f64).log2();
+        Ok(bits)
     }
+}
+
+fn check_length(length: usize) -> anyhow::Result<()> {
+    if length == 0 {
+        anyhow::bail!("password length must be at least 1");
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        anyhow::bail!(
+            "password length {} exceeds the maximum of {}",
+            length,
+            MAX_PASSWORD_LENGTH
+        );
+    }
+    Ok(())
+}
+
+fn literal(c: char) -> anyhow::Result<Vec<u8>> {
+    if !c.is_ascii_graphic() {
+        anyhow::bail!("pattern must be printable ASCII, found {:?}", c);
+    }
+    Ok(vec![c as u8])
+}
+
+/// Strength details of a generated password
+#[derive(Debug, Serialize)]
+pub struct PasswordReport {
+    pub password: String,
+    pub length: usize,
+    pub entropy_bits: f64,
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub crack_time_online_throttled: String,
+    pub crack_time_online: String,
+    pub crack_time_offline_slow_hash: String,
+    pub crack_time_offline_fast_hash: String,
+}
+
+impl PasswordReport {
+    pub fn try_new(password: String, entropy_bits: f64) -> anyhow::Result<Self> {
+        // a prefix is never stronger than the whole password, so the estimate stays conservative
+        let prefix: String = password.chars().take(MAX_ESTIMATE_LENGTH).collect();
+        let estimate = zxcvbn(&prefix, &[])?;
+        let times = estimate.crack_times();
+        Ok(Self {
+            length: password.chars().count(),
+            password,
+            entropy_bits,
+            score: estimate.score(),
+            guesses_log10: estimate.guesses_log10(),
+            crack_time_online_throttled: times.online_throttling_100_per_hour().to_string(),
+            crack_time_online: times.online_no_throttling_10_per_second().to_string(),
+            crack_time_offline_slow_hash: times.offline_slow_hashing_1e4_per_second().to_string(),
+            crack_time_offline_fast_hash: times.offline_fast_hashing_1e10_per_second().to_string(),
+        })
+    }
+}
 
-    for _ in 0..length - password.len() as u8 {
+pub fn process_genpass(policy: &PasswordPolicy) -> anyhow::Result<String> {
+    let mut rng = OsRng;
+    if !matches!(policy.mode, PasswordMode::Random) {
+        let password = policy
+            .positions()?
+            .iter()
+            .filter_map(|pool| pool.choose(&mut rng).copied())
+            .collect();
+        return Ok(String::from_utf8(password)?);
+    }
+
+    let (chars, required) = policy.resolve()?;
+    let mut password: Vec<u8> = Vec::with_capacity(policy.length);
+
+    for class in &required {
+        for _ in 0..class.min {
+            if let Some(c) = class.chars.choose(&mut rng) {
+                password.push(*c);
+            }
+        }
+    }
+
+    while password.len() < policy.length {
         if let Some(c) = chars.choose(&mut rng) {
             password.push(*c);
         } else {
@@ -58,3 +382,235 @@ pub fn process_genpass(
 
     Ok(password)
 }
+
+/// Generate `count` passwords along with their strength details,
+/// regenerating any password found in the `pwned` dump
+pub fn process_genpass_batch(
+    policy: &PasswordPolicy,
+    count: u32,
+    pwned: Option<&PwnedPasswords>,
+) -> anyhow::Result<Vec<PasswordReport>> {
+    let entropy_bits = policy.entropy_bits()?;
+    (0..count)
+        .map(|_| {
+            let password = genpass_unbreached(policy, pwned)?;
+            PasswordReport::try_new(password, entropy_bits)
+        })
+        .collect()
+}
+
+fn genpass_unbreached(
+    policy: &PasswordPolicy,
+    pwned: Option<&PwnedPasswords>,
+) -> anyhow::Result<String> {
+    let Some(pwned) = pwned else {
+        return process_genpass(policy);
+    };
+    for _ in 0..MAX_PWNED_RETRIES {
+        let password = process_genpass(policy)?;
+        if pwned.lookup(&password)? == 0 {
+            return Ok(password);
+        }
+    }
+    anyhow::bail!(
+        "every generated password was found in the breached passwords file, the policy is too weak"
+    )
+}
+
+pub fn process_genpass_output(
+    reports: &[PasswordReport],
+    format: GenPassOutputFormat,
+) -> anyhow::Result<String> {
+    let content = match format {
+        GenPassOutputFormat::Plain => reports
+            .iter()
+            .map(|report| report.password.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        GenPassOutputFormat::Json => serde_json::to_string_pretty(reports)?,
+        GenPassOutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for report in reports {
+                writer.serialize(report)?;
+            }
+            // the writer terminates every record, the caller prints the final newline
+            let content = String::from_utf8(writer.into_inner()?)?;
+            content.trim_end().to_string()
+        }
+    };
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genpass_min_counts_and_exclude() {
+        let policy = PasswordPolicy {
+            length: 12,
+            min_upper: Some(2),
+            min_symbols: Some(3),
+            exclude: "{}[]".into(),
+            ..Default::default()
+        };
+        let password = process_genpass(&policy).unwrap();
+        assert_eq!(password.len(), 12);
+        assert!(password.chars().filter(|c| c.is_ascii_uppercase()).count() >= 2);
+        assert!(password.bytes().filter(|c| SYMBOL.contains(c)).count() >= 3);
+        assert!(!password.contains(|c| "{}[]0OlI".contains(c)));
+    }
+
+    #[test]
+    fn test_genpass_charset() {
+        let policy = PasswordPolicy {
+            charset: Some("abc123".into()),
+            min_numbers: Some(4),
+            ..Default::default()
+        };
+        let password = process_genpass(&policy).unwrap();
+        assert!(password.chars().all(|c| "abc123".contains(c)));
+        assert!(password.chars().filter(|c| c.is_ascii_digit()).count() >= 4);
+    }
+
+    #[test]
+    fn test_genpass_rejects_impossible_policies() {
+        let too_short = PasswordPolicy {
+            length: 3,
+            ..Default::default()
+        };
+        assert!(process_genpass(&too_short).is_err());
+
+        let disabled = PasswordPolicy {
+            symbol: false,
+            min_symbols: Some(1),
+            ..Default::default()
+        };
+        assert!(process_genpass(&disabled).is_err());
+
+        let excluded = PasswordPolicy {
+            exclude: "123456789".into(),
+            ..Default::default()
+        };
+        assert!(process_genpass(&excluded).is_err());
+
+        let no_upper = PasswordPolicy {
+            charset: Some("abc".into()),
+            min_upper: Some(1),
+            ..Default::default()
+        };
+        assert!(process_genpass(&no_upper).is_err());
+    }
+
+    #[test]
+    fn test_genpass_pronounceable() {
+        let policy = PasswordPolicy {
+            mode: PasswordMode::Pronounceable,
+            length: 9,
+            ..Default::default()
+        };
+        let password = process_genpass(&policy).unwrap();
+        assert_eq!(password.len(), 9);
+        for (i, c) in password.bytes().enumerate() {
+            assert_eq!(VOWELS.contains(&c), i % 2 == 1);
+        }
+        // 5 consonants out of 20 ("l" is ambiguous), 4 vowels out of 5
+        let expected = 5.0 * 20f64.log2() + 4.0 * 5f64.log2();
+        assert!((policy.entropy_bits().unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_genpass_pattern() {
+        let policy = PasswordPolicy {
+            mode: PasswordMode::Pattern("Cvccvc-99-\\9".into()),
+            ..Default::default()
+        };
+        let password = process_genpass(&policy).unwrap();
+        assert_eq!(password.len(), 11);
+        assert!(password.as_bytes()[0].is_ascii_uppercase());
+        assert!(VOWELS.contains(&password.as_bytes()[1]));
+        assert_eq!(&password[6..7], "-");
+        assert!(password[7..9].chars().all(|c| c.is_ascii_digit()));
+        assert!(password.ends_with("-9"));
+        // literals are free, "l" and "0" are ambiguous
+        let expected = 21f64.log2() + 2.0 * 5f64.log2() + 3.0 * 20f64.log2() + 2.0 * 9f64.log2();
+        assert!((policy.entropy_bits().unwrap() - expected).abs() < 1e-9);
+
+        let bad = PasswordPolicy {
+            mode: PasswordMode::Pattern("99".into()),
+            exclude: "123456789".into(),
+            ..Default::default()
+        };
+        assert!(process_genpass(&bad).is_err());
+
+        // a disabled class is not silently used by the template
+        for mode in [
+            PasswordMode::Pattern("Cv99".into()),
+            PasswordMode::Pronounceable,
+        ] {
+            let no_number = PasswordPolicy {
+                mode,
+                number: false,
+                ..Default::default()
+            };
+            assert!(process_genpass(&no_number).is_err());
+        }
+    }
+
+    #[test]
+    fn test_genpass_length_bounds() {
+        for length in [0, MAX_PASSWORD_LENGTH + 1] {
+            let policy = PasswordPolicy {
+                length,
+                ..Default::default()
+            };
+            assert!(process_genpass(&policy).is_err());
+        }
+
+        let policy = PasswordPolicy {
+            length: 3000,
+            ..Default::default()
+        };
+        assert_eq!(process_genpass(&policy).unwrap().len(), 3000);
+        let report = process_genpass_batch(&policy, 1, None).unwrap();
+        assert_eq!(report[0].length, 3000);
+    }
+
+    #[test]
+    fn test_genpass_rejects_breached() {
+        let pwned = PwnedPasswords::open("fixtures/pwned.txt").unwrap();
+        // "111111" is the only password this policy can produce, and it is breached
+        let breached = PasswordPolicy {
+            length: 6,
+            charset: Some("1".into()),
+            ..Default::default()
+        };
+        assert!(process_genpass_batch(&breached, 1, None).is_ok());
+        assert!(process_genpass_batch(&breached, 1, Some(&pwned)).is_err());
+
+        let policy = PasswordPolicy::default();
+        assert!(process_genpass_batch(&policy, 2, Some(&pwned)).is_ok());
+    }
+
+    #[test]
+    fn test_genpass_batch_output() {
+        let policy = PasswordPolicy {
+            length: 12,
+            number: false,
+            symbol: false,
+            ..Default::default()
+        };
+        // one required char from each class, the other ten from all 49 chars
+        let expected = 24f64.log2() + 25f64.log2() + 10.0 * 49f64.log2();
+        assert!((policy.entropy_bits().unwrap() - expected).abs() < 1e-9);
+
+        let reports = process_genpass_batch(&policy, 3, None).unwrap();
+        assert_eq!(reports.len(), 3);
+        let csv = process_genpass_output(&reports, GenPassOutputFormat::Csv).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.starts_with("password,length,entropy_bits,score"));
+        let json = process_genpass_output(&reports, GenPassOutputFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["length"], 12);
+    }
+}
@@ -0,0 +1,310 @@
+use std::{io::Read, path::Path};
+
+use md5::Md5;
+use rayon::prelude::*;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{get_reader, HashAlgorithm};
+
+/// Outcome of one line of a checksum file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    /// the file could not be opened or read
+    Unreadable,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+}
+
+impl HashAlgorithm {
+    /// Guess the algorithm of a checksum file from the digest length, 256-bit digests
+    /// are SHA-256 unless the file is named like `b3sum` output: `B3SUMS`, `b3sums.txt`
+    /// or `<name>.b3`
+    fn detect(digest: &str, checksum_file: &str) -> anyhow::Result<Self> {
+        let path = Path::new(checksum_file);
+        let b3 = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().to_ascii_lowercase().starts_with("b3"))
+            || path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("b3"));
+        match digest.len() {
+            16 => Ok(HashAlgorithm::Xxh3),
+            32 => Ok(HashAlgorithm::Md5),
+            40 => Ok(HashAlgorithm::Sha1),
+            64 if b3 => Ok(HashAlgorithm::Blake3),
+            64 => Ok(HashAlgorithm::Sha256),
+            128 => Ok(HashAlgorithm::Sha512),
+            n => anyhow::bail!(
+                "cannot tell the algorithm of a {}-digit checksum, pass --algo",
+                n
+            ),
+        }
+    }
+}
+
+/// Checksum lines in coreutils format for every file, `-` is stdin.
+/// Files are hashed in parallel, and large files are hashed in parallel with blake3
+pub fn process_hash(files: &[String], algo: HashAlgorithm) -> anyhow::Result<Vec<String>> {
+    files
+        .par_iter()
+        .map(|name| {
+            let digest = hash_file(name, algo)?;
+            // xxhsum tags XXH3 digests, and `xxhsum -c` expects the tag back
+            let digest = match algo {
+                HashAlgorithm::Xxh3 => format!("XXH3_{}", digest),
+                _ => digest,
+            };
+            Ok(format_checksum_line(&digest, name))
+        })
+        .collect()
+}
+
+/// Verify every file listed in a `sha256sum`/`b3sum` style checksum file
+pub fn process_hash_check(
+    checksum_file: &str,
+    algo: Option<HashAlgorithm>,
+) -> anyhow::Result<Vec<CheckResult>> {
+    let mut data = String::new();
+    get_reader(checksum_file)?.read_to_string(&mut data)?;
+    let lines = data
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .map(parse_checksum_line)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    lines
+        .into_par_iter()
+        .map(|(expected, name)| {
+            let algo = match algo {
+                Some(algo) => algo,
+                None => HashAlgorithm::detect(&expected, checksum_file)?,
+            };
+            let status = match hash_file(&name, algo) {
+                Ok(actual) if actual.eq_ignore_ascii_case(&expected) => CheckStatus::Ok,
+                Ok(_) => CheckStatus::Failed,
+                Err(_) => CheckStatus::Unreadable,
+            };
+            Ok(CheckResult { name, status })
+        })
+        .collect()
+}
+
+/// Hex digest of a file, or of stdin for `-`
+pub(crate) fn hash_file(name: &str, algo: HashAlgorithm) -> anyhow::Result<String> {
+    if let (HashAlgorithm::Blake3, false) = (algo, name == "-") {
+        // memory-maps large files and hashes them on all cores
+        let hash = blake3::Hasher::new().update_mmap_rayon(name)?.finalize();
+        return Ok(hash.to_hex().to_string());
+    }
+    let mut reader = get_reader(name)?;
+    let digest = match algo {
+        HashAlgorithm::Blake3 => blake3::Hasher::new()
+            .update_reader(reader)?
+            .finalize()
+            .as_bytes()
+            .to_vec(),
+        HashAlgorithm::Sha256 => digest_reader::<Sha256>(&mut reader)?,
+        HashAlgorithm::Sha512 => digest_reader::<Sha512>(&mut reader)?,
+        HashAlgorithm::Sha1 => digest_reader::<Sha1>(&mut reader)?,
+        HashAlgorithm::Md5 => digest_reader::<Md5>(&mut reader)?,
+        HashAlgorithm::Xxh3 => {
+            let mut hasher = Xxh3::new();
+            let mut buf = [0u8; 64 * 1024];
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            // canonical big-endian form, as xxhsum prints it
+            hasher.digest().to_be_bytes().to_vec()
+        }
+    };
+    Ok(hex::encode(digest))
+}
+
+fn digest_reader<D: Digest + std::io::Write>(reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    std::io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// `<digest>  <name>`; names with a backslash or newline are escaped
+/// and the line starts with `\`, as coreutils and b3sum do
+pub(crate) fn format_checksum_line(digest: &str, name: &str) -> String {
+    if name.contains(['\\', '\n']) {
+        let name = name.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("\\{}  {}", digest, name)
+    } else {
+        format!("{}  {}", digest, name)
+    }
+}
+
+/// The digest (lower-cased) and name of a checksum line, text (`  `) or binary (` *`) mode
+pub(crate) fn parse_checksum_line(line: &str) -> anyhow::Result<(String, String)> {
+    let (escaped, rest) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (digest, name) = rest
+        .split_once(' ')
+        .and_then(|(digest, name)| {
+            let name = name.strip_prefix([' ', '*'])?;
+            Some((digest, name))
+        })
+        .ok_or_else(|| anyhow::anyhow!("malformed checksum line: {}", line))?;
+    let digest = digest.strip_prefix("XXH3_").unwrap_or(digest);
+    if digest.is_empty() || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        anyhow::bail!("malformed checksum line: {}", line);
+    }
+    let name = if escaped {
+        unescape(name)?
+    } else {
+        name.to_string()
+    };
+    Ok((digest.to_ascii_lowercase(), name))
+}
+
+fn unescape(name: &str) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            _ => anyhow::bail!("invalid escape in checksum file name: {}", name),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_checksums(path: &Path, lines: &[String]) {
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn test_hash_known_digests() {
+        let file = "fixtures/b64.txt";
+        let data = fs::read(file).unwrap();
+        let expected = [
+            (
+                HashAlgorithm::Blake3,
+                blake3::hash(&data).to_hex().to_string(),
+            ),
+            (HashAlgorithm::Sha256, hex::encode(Sha256::digest(&data))),
+            (HashAlgorithm::Md5, hex::encode(Md5::digest(&data))),
+        ];
+        for (algo, digest) in expected {
+            assert_eq!(hash_file(file, algo).unwrap(), digest);
+        }
+        // `printf abc | sha1sum` and `xxhsum -H3`
+        let dir = tempfile::tempdir().unwrap();
+        let abc = dir.path().join("abc");
+        fs::write(&abc, "abc").unwrap();
+        let abc = abc.to_str().unwrap();
+        assert_eq!(
+            hash_file(abc, HashAlgorithm::Sha1).unwrap(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hash_file(abc, HashAlgorithm::Xxh3).unwrap(),
+            "78af5f94892f3950"
+        );
+        assert_eq!(
+            process_hash(&[abc.to_string()], HashAlgorithm::Xxh3).unwrap(),
+            [format!("XXH3_78af5f94892f3950  {}", abc)]
+        );
+    }
+
+    #[test]
+    fn test_detect_algorithm() {
+        let sha256 = hex::encode(Sha256::digest(b""));
+        for (file, algo) in [
+            ("B3SUMS", HashAlgorithm::Blake3),
+            ("dist/b3sums.txt", HashAlgorithm::Blake3),
+            ("release.B3", HashAlgorithm::Blake3),
+            ("SHA256SUMS", HashAlgorithm::Sha256),
+            ("SHA256SUMS-glib3", HashAlgorithm::Sha256),
+            ("lib3.sums", HashAlgorithm::Sha256),
+        ] {
+            assert_eq!(HashAlgorithm::detect(&sha256, file).unwrap(), algo);
+        }
+        assert_eq!(
+            HashAlgorithm::detect("78af5f94892f3950", "SUMS").unwrap(),
+            HashAlgorithm::Xxh3
+        );
+    }
+
+    #[test]
+    fn test_hash_check() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let ok = dir.join("ok.txt").to_string_lossy().to_string();
+        let bad = dir.join("bad.txt").to_string_lossy().to_string();
+        let gone = dir.join("gone.txt").to_string_lossy().to_string();
+        for name in [&ok, &bad, &gone] {
+            fs::write(name, "hello").unwrap();
+        }
+        let files = [ok.clone(), bad.clone(), gone.clone()];
+        for algo in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let sums = dir.join(if algo == HashAlgorithm::Blake3 {
+                "B3SUMS"
+            } else {
+                "SHA256SUMS"
+            });
+            write_checksums(&sums, &process_hash(&files, algo).unwrap());
+            fs::write(&bad, "changed").unwrap();
+            fs::remove_file(&gone).unwrap();
+
+            // the algorithm is detected from the digest length and file name
+            let results = process_hash_check(sums.to_str().unwrap(), None).unwrap();
+            let statuses: Vec<_> = results.iter().map(|r| r.status).collect();
+            assert_eq!(
+                statuses,
+                [
+                    CheckStatus::Ok,
+                    CheckStatus::Failed,
+                    CheckStatus::Unreadable
+                ]
+            );
+            fs::write(&bad, "hello").unwrap();
+            fs::write(&gone, "hello").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_checksum_line() {
+        let line = format_checksum_line("ab", "a\\b\nc");
+        assert_eq!(line, "\\ab  a\\\\b\\nc");
+        assert_eq!(
+            parse_checksum_line(&line).unwrap(),
+            ("ab".into(), "a\\b\nc".into())
+        );
+        assert_eq!(
+            parse_checksum_line("AB *bin").unwrap(),
+            ("ab".into(), "bin".into())
+        );
+        assert!(parse_checksum_line("zz  x").is_err());
+        assert!(parse_checksum_line("ab x").is_err());
+    }
+}
@@ -1,8 +1,13 @@
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    net::SocketAddr,
+    path::{Component, PathBuf},
+    sync::Arc,
+};
 
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
     Router,
 };
@@ -11,6 +16,8 @@ use tokio::fs;
 use tower_http::{compression::CompressionLayer, services::ServeDir};
 use tracing::{info, warn};
 
+use crate::{process_bao_slice, BAO_OUTBOARD_EXT};
+
 #[derive(Debug)]
 struct HttpServeState {
     path: PathBuf,
@@ -29,6 +36,7 @@ pub async fn process_http_serve(path: PathBuf, port: u16) -> anyhow::Result<()>
     let app = Router::new()
         .route("/", get(index_handler))
         .nest_service("/tower", dir_service)
+        .route("/bao/*path", get(bao_handler))
         .route("/*path", get(file_handler))
         .layer(
             CompressionLayer::new()
@@ -55,7 +63,6 @@ async fn file_handler(
     // if it is a directory, list all files/subdirectories
     // as <li><a href="/path/to/file">file name</a></li>
     // <html><body><ul>...</ul></body></html>
-    format!("visit state: {:?} path: {:?} ", state.path, p);
     let path = state.path.join(p);
     info!("Client Requesting {:?}", path);
     if !path.exists() {
@@ -74,6 +81,97 @@ async fn file_handler(
     }
 }
 
+/// Serve a Bao slice of the requested range, verifiable against the root hash
+/// with `rcli text bao decode`. Needs the `<file>.obao` outboard next to the file
+async fn bao_handler(
+    State(state): State<Arc<HttpServeState>>,
+    Path(p): Path<PathBuf>,
+    headers: HeaderMap,
+) -> Response {
+    // `..`, a root or a prefix would let the request leave the served directory
+    if !p.components().all(|c| matches!(c, Component::Normal(_))) {
+        let msg = format!("invalid path: {}", p.display());
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
+    let path = state.path.join(p);
+    let outboard = PathBuf::from(format!("{}.{}", path.display(), BAO_OUTBOARD_EXT));
+    info!("Client Requesting bao slice of {:?}", path);
+    let content_len = match fs::metadata(&path).await {
+        Ok(meta) if meta.is_file() && outboard.exists() => meta.len(),
+        _ => {
+            let msg = format!("file or outboard not found: {}", path.display());
+            return (StatusCode::NOT_FOUND, msg).into_response();
+        }
+    };
+    let range = headers.get(header::RANGE).map(|range| {
+        range
+            .to_str()
+            .ok()
+            .and_then(|range| parse_range(range, content_len))
+    });
+    let (start, len) = match range {
+        None => (0, content_len),
+        Some(Some(range)) => range,
+        Some(None) => {
+            return (StatusCode::RANGE_NOT_SATISFIABLE, "unsupported range").into_response()
+        }
+    };
+
+    let slice = tokio::task::spawn_blocking(move || {
+        let mut slice = vec![];
+        process_bao_slice(
+            &path.to_string_lossy(),
+            &outboard.to_string_lossy(),
+            start,
+            len,
+            &mut slice,
+        )
+        .map(|_| slice)
+    })
+    .await;
+    match slice {
+        Ok(Ok(slice)) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (
+                    header::HeaderName::from_static("x-bao-start"),
+                    start.to_string(),
+                ),
+                (
+                    header::HeaderName::from_static("x-bao-len"),
+                    len.to_string(),
+                ),
+            ],
+            slice,
+        )
+            .into_response(),
+        Ok(Err(e)) => {
+            warn!("error slicing file: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// A single `bytes=` range as start and length, clamped to the content. Multiple ranges
+/// and ranges that start at or past the end are not satisfiable
+fn parse_range(range: &str, content_len: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.trim().split_once('-')?;
+    let (start, end) = if start.is_empty() {
+        // the last `end` bytes
+        let suffix = end.parse::<u64>().ok()?.min(content_len);
+        (content_len - suffix, content_len)
+    } else {
+        let end = match end {
+            "" => content_len,
+            end => end.parse::<u64>().ok()?.checked_add(1)?.min(content_len),
+        };
+        (start.parse::<u64>().ok()?, end)
+    };
+    (start < content_len && end > start).then(|| (start, end - start))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,4 +189,85 @@ mod tests {
         assert_eq!(status, StatusCode::OK);
         assert!(response.contains("[package]"))
     }
+
+    #[tokio::test]
+    async fn test_bao_handler() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let content: Vec<u8> = (0..10000u32).map(|i| i as u8).collect();
+        let file = dir.join("data.bin");
+        std::fs::write(&file, &content).unwrap();
+        let (hash, outboard) = crate::process_bao_encode(&file.to_string_lossy()).unwrap();
+        std::fs::write(dir.join("data.bin.obao"), outboard).unwrap();
+
+        let state = Arc::new(HttpServeState { path: dir.clone() });
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, "bytes=3000-3999".parse().unwrap());
+        let response = bao_handler(
+            State(state.clone()),
+            Path(PathBuf::from("data.bin")),
+            headers,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.len() < content.len());
+
+        let slice = dir.join("data.slice");
+        std::fs::write(&slice, &body).unwrap();
+        let mut decoded = vec![];
+        crate::process_bao_decode(
+            &slice.to_string_lossy(),
+            None,
+            &hash,
+            3000,
+            Some(1000),
+            &mut decoded,
+        )
+        .unwrap();
+        assert_eq!(decoded, &content[3000..4000]);
+
+        // a range starting at the end is not satisfiable
+        for range in ["bytes=10000-", "bytes=10000-10999"] {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, range.parse().unwrap());
+            let response = bao_handler(
+                State(state.clone()),
+                Path(PathBuf::from("data.bin")),
+                headers,
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        }
+
+        // the file and its outboard exist, but outside the served directory
+        std::fs::create_dir(dir.join("served")).unwrap();
+        let state = Arc::new(HttpServeState {
+            path: dir.join("served"),
+        });
+        for p in ["../data.bin", file.to_str().unwrap()] {
+            let response = bao_handler(
+                State(state.clone()),
+                Path(PathBuf::from(p)),
+                HeaderMap::new(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 100)));
+        assert_eq!(parse_range("bytes=900-", 1000), Some((900, 100)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 100)));
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+        assert_eq!(parse_range("bytes=900-2000", 1000), Some((900, 100)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=1000-1999", 1000), None);
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+    }
 }
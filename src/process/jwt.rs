@@ -0,0 +1,414 @@
+use std::{
+    fs,
+    io::Read,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rsa::{
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    pkcs1v15,
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    signature::{SignatureEncoding, Signer, Verifier},
+    RsaPrivateKey, RsaPublicKey,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+
+use super::{
+    ecdsa::{EcdsaCurve, EcdsaSigner, EcdsaVerifier},
+    key_crypt::read_key,
+    text::{Ed25519Signer, Ed25519Verifier, HmacHash, HmacKey, TextSign, TextVerify},
+};
+use crate::{CliError, JwtAlgorithm, PassphraseSource};
+
+/// Claims set from the command line, `iat` is always added
+#[derive(Debug, Default)]
+pub struct JwtClaims {
+    pub sub: Option<String>,
+    pub aud: Option<String>,
+    pub iss: Option<String>,
+    /// lifetime, from now
+    pub exp: Option<Duration>,
+    /// delay before the token becomes valid, from now
+    pub nbf: Option<Duration>,
+    /// `--claim k=v`, the value is JSON when it parses as such and a string otherwise
+    pub extra: Vec<(String, Value)>,
+}
+
+/// What a token must satisfy besides its signature
+#[derive(Debug, Default)]
+pub struct JwtValidation {
+    pub aud: Option<String>,
+    pub iss: Option<String>,
+    /// clock skew allowed on `exp` and `nbf`
+    pub leeway: Duration,
+}
+
+/// A decoded token, printed as is
+#[derive(Debug, Serialize)]
+pub struct JwtToken {
+    pub header: Value,
+    pub claims: Value,
+}
+
+/// RSASSA-PKCS1-v1_5 with SHA-256. The `rsa` crate's private key operations are not
+/// constant time (RUSTSEC-2023-0071, the Marvin attack), so a signer whose timing can be
+/// measured over the network may leak the key; prefer EdDSA or ES256 there
+struct RsaSigner {
+    key: pkcs1v15::SigningKey<Sha256>,
+}
+
+struct RsaVerifier {
+    key: pkcs1v15::VerifyingKey<Sha256>,
+}
+
+impl TextSign for RsaSigner {
+    fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Ok(self.key.try_sign(&buf)?.to_vec())
+    }
+}
+
+impl TextVerify for RsaVerifier {
+    fn verify(&self, mut reader: impl Read, sig: &[u8]) -> anyhow::Result<bool> {
+        let sig = pkcs1v15::Signature::try_from(sig)
+            .map_err(|e| CliError::InvalidSignature(anyhow::anyhow!("invalid signature: {}", e)))?;
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Ok(self.key.verify(&buf, &sig).is_ok())
+    }
+}
+
+impl RsaSigner {
+    /// PKCS#8 or PKCS#1, PEM or DER, as written by OpenSSL
+    fn try_new(key: &[u8]) -> anyhow::Result<Self> {
+        let key = match std::str::from_utf8(key) {
+            Ok(pem) if pem.contains("-----BEGIN") => RsaPrivateKey::from_pkcs8_pem(pem)
+                .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+                .map_err(|e| anyhow::anyhow!(e)),
+            _ => RsaPrivateKey::from_pkcs8_der(key)
+                .or_else(|_| RsaPrivateKey::from_pkcs1_der(key))
+                .map_err(|e| anyhow::anyhow!(e)),
+        }
+        .context("invalid RSA private key")
+        .map_err(CliError::InvalidKey)?;
+        Ok(Self {
+            key: pkcs1v15::SigningKey::new(key),
+        })
+    }
+
+    fn load(path: impl AsRef<Path>, passphrase: &PassphraseSource) -> anyhow::Result<Self> {
+        let key = read_key(path, passphrase)?;
+        Self::try_new(&key)
+    }
+}
+
+impl RsaVerifier {
+    /// SPKI or PKCS#1, PEM or DER
+    fn try_new(key: &[u8]) -> anyhow::Result<Self> {
+        let key = match std::str::from_utf8(key) {
+            Ok(pem) if pem.contains("-----BEGIN") => RsaPublicKey::from_public_key_pem(pem)
+                .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+                .map_err(|e| anyhow::anyhow!(e)),
+            _ => RsaPublicKey::from_public_key_der(key)
+                .or_else(|_| RsaPublicKey::from_pkcs1_der(key))
+                .map_err(|e| anyhow::anyhow!(e)),
+        }
+        .context("invalid RSA public key")
+        .map_err(CliError::InvalidKey)?;
+        Ok(Self {
+            key: pkcs1v15::VerifyingKey::new(key),
+        })
+    }
+
+    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let key = fs::read(path)?;
+        Self::try_new(&key)
+    }
+}
+
+impl JwtClaims {
+    fn to_json(&self, now: u64) -> Value {
+        let mut claims = Map::new();
+        let strings = [("sub", &self.sub), ("aud", &self.aud), ("iss", &self.iss)];
+        for (name, value) in strings {
+            if let Some(value) = value {
+                claims.insert(name.into(), json!(value));
+            }
+        }
+        claims.insert("iat".into(), json!(now));
+        if let Some(nbf) = self.nbf {
+            claims.insert("nbf".into(), json!(now + nbf.as_secs()));
+        }
+        if let Some(exp) = self.exp {
+            claims.insert("exp".into(), json!(now + exp.as_secs()));
+        }
+        // explicit claims win over the ones above
+        for (name, value) in &self.extra {
+            claims.insert(name.clone(), value.clone());
+        }
+        Value::Object(claims)
+    }
+}
+
+impl JwtValidation {
+    fn validate(&self, claims: &Value, now: u64) -> anyhow::Result<()> {
+        let leeway = self.leeway.as_secs();
+        if let Some(exp) = numeric_claim(claims, "exp")? {
+            if now > exp.saturating_add(leeway) {
+                anyhow::bail!("token expired at {}", exp);
+            }
+        }
+        if let Some(nbf) = numeric_claim(claims, "nbf")? {
+            if now.saturating_add(leeway) < nbf {
+                anyhow::bail!("token is not valid before {}", nbf);
+            }
+        }
+        if let Some(aud) = &self.aud {
+            // a single audience or an array of them
+            let matches = match &claims["aud"] {
+                Value::String(s) => s == aud,
+                Value::Array(values) => values.iter().any(|v| v.as_str() == Some(aud)),
+                _ => false,
+            };
+            if !matches {
+                anyhow::bail!("token audience does not include {}", aud);
+            }
+        }
+        if let Some(iss) = &self.iss {
+            if claims["iss"].as_str() != Some(iss) {
+                anyhow::bail!("token issuer is not {}", iss);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn numeric_claim(claims: &Value, name: &str) -> anyhow::Result<Option<u64>> {
+    match &claims[name] {
+        Value::Null => Ok(None),
+        Value::Number(n) => match n.as_u64().or_else(|| n.as_f64().map(|f| f as u64)) {
+            Some(n) => Ok(Some(n)),
+            None => anyhow::bail!("{} claim is not a valid timestamp", name),
+        },
+        _ => anyhow::bail!("{} claim is not a number", name),
+    }
+}
+
+/// Sign the claims into a compact JWS
+pub fn process_jwt_sign(
+    key: &str,
+    alg: JwtAlgorithm,
+    passphrase: &PassphraseSource,
+    claims: &JwtClaims,
+) -> anyhow::Result<String> {
+    let header = json!({ "alg": Into::<&str>::into(alg), "typ": "JWT" });
+    let claims = claims.to_json(now()?);
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?)
+    );
+    let mut reader = signing_input.as_bytes();
+    let sig = match alg {
+        JwtAlgorithm::Hs256 => HmacKey::load(key, HmacHash::Sha256, passphrase)?.sign(&mut reader),
+        JwtAlgorithm::EdDsa => Ed25519Signer::load(key, passphrase)?.sign(&mut reader),
+        // JWS wants the raw r || s form
+        JwtAlgorithm::Es256 => EcdsaSigner::load(key, EcdsaCurve::P256, passphrase)?
+            .der(false)
+            .sign(&mut reader),
+        JwtAlgorithm::Rs256 => RsaSigner::load(key, passphrase)?.sign(&mut reader),
+    }?;
+    Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(sig)))
+}
+
+/// Check the signature with the key for `alg`, then the time and audience claims.
+/// The header must name `alg` too, so a token cannot pick a weaker algorithm itself
+pub fn process_jwt_verify(
+    token: &str,
+    key: &str,
+    alg: JwtAlgorithm,
+    passphrase: &PassphraseSource,
+    validation: &JwtValidation,
+) -> anyhow::Result<JwtToken> {
+    let token = token.trim();
+    let (signing_input, sig) = token
+        .rsplit_once('.')
+        .ok_or_else(|| anyhow::anyhow!("token must have three parts"))?;
+    let decoded = process_jwt_decode(token)?;
+    let sig = URL_SAFE_NO_PAD
+        .decode(sig)
+        .map_err(|e| CliError::InvalidSignature(anyhow::anyhow!("invalid signature: {}", e)))?;
+
+    let header_alg = decoded.header["alg"].as_str().unwrap_or_default();
+    if header_alg != Into::<&str>::into(alg) {
+        return Err(
+            anyhow::Error::from(CliError::VerificationFailed).context(format!(
+                "token algorithm {:?} does not match {}",
+                header_alg, alg
+            )),
+        );
+    }
+
+    let reader = signing_input.as_bytes();
+    let valid = match alg {
+        JwtAlgorithm::Hs256 => {
+            HmacKey::load(key, HmacHash::Sha256, passphrase)?.verify(reader, &sig)?
+        }
+        JwtAlgorithm::EdDsa => Ed25519Verifier::load(key)?.verify(reader, &sig)?,
+        JwtAlgorithm::Es256 => {
+            if sig.len() != 64 {
+                let err = anyhow::anyhow!("invalid signature: ES256 signatures are 64 bytes");
+                return Err(CliError::InvalidSignature(err).into());
+            }
+            EcdsaVerifier::load(key, EcdsaCurve::P256)?.verify(reader, &sig)?
+        }
+        JwtAlgorithm::Rs256 => RsaVerifier::load(key)?.verify(reader, &sig)?,
+    };
+    if !valid {
+        return Err(
+            anyhow::Error::from(CliError::VerificationFailed).context("token signature is invalid")
+        );
+    }
+    validation
+        .validate(&decoded.claims, now()?)
+        .map_err(|e| anyhow::Error::from(CliError::VerificationFailed).context(e.to_string()))?;
+    Ok(decoded)
+}
+
+/// Split and decode a token without checking anything
+pub fn process_jwt_decode(token: &str) -> anyhow::Result<JwtToken> {
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let [header, claims, _] = parts[..] else {
+        anyhow::bail!("token must have three parts, found {}", parts.len());
+    };
+    let decode = |part: &str, what: &str| -> anyhow::Result<Value> {
+        let json = URL_SAFE_NO_PAD
+            .decode(part)
+            .with_context(|| format!("token {} is not base64url", what))?;
+        serde_json::from_slice(&json).with_context(|| format!("token {} is not JSON", what))
+    };
+    Ok(JwtToken {
+        header: decode(header, "header")?,
+        claims: decode(claims, "claims")?,
+    })
+}
+
+fn now() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: [(JwtAlgorithm, &str, &str); 4] = [
+        (
+            JwtAlgorithm::Hs256,
+            "fixtures/jwt_secret.txt",
+            "fixtures/jwt_secret.txt",
+        ),
+        (
+            JwtAlgorithm::EdDsa,
+            "fixtures/ed25519.sk",
+            "fixtures/ed25519.pk",
+        ),
+        (
+            JwtAlgorithm::Es256,
+            "fixtures/p256_openssl.pem",
+            "fixtures/p256_openssl.pub.pem",
+        ),
+        (
+            JwtAlgorithm::Rs256,
+            "fixtures/rsa_openssl.pem",
+            "fixtures/rsa_openssl.pub.pem",
+        ),
+    ];
+
+    #[test]
+    fn test_jwt_sign_verify() {
+        let passphrase = PassphraseSource::default();
+        let claims = JwtClaims {
+            sub: Some("alice".into()),
+            aud: Some("rcli".into()),
+            exp: Some(Duration::from_secs(3600)),
+            extra: vec![("admin".into(), json!(true))],
+            ..Default::default()
+        };
+        let validation = JwtValidation {
+            aud: Some("rcli".into()),
+            ..Default::default()
+        };
+        for (alg, sk, pk) in KEYS {
+            let token = process_jwt_sign(sk, alg, &passphrase, &claims).unwrap();
+            let decoded = process_jwt_verify(&token, pk, alg, &passphrase, &validation).unwrap();
+            assert_eq!(decoded.header["alg"], Into::<&str>::into(alg));
+            let line = format!("{}\n", token);
+            assert!(process_jwt_verify(&line, pk, alg, &passphrase, &validation).is_ok());
+            assert_eq!(decoded.claims["sub"], "alice");
+            assert_eq!(decoded.claims["admin"], true);
+
+            // flip a bit of the signature
+            let mut forged = token.into_bytes();
+            let last = forged.len() - 2;
+            forged[last] = if forged[last] == b'A' { b'B' } else { b'A' };
+            let forged = String::from_utf8(forged).unwrap();
+            let err = process_jwt_verify(&forged, pk, alg, &passphrase, &validation).unwrap_err();
+            assert_eq!(crate::exit_code(&err), crate::EXIT_VERIFY_FAILED);
+        }
+    }
+
+    #[test]
+    fn test_jwt_verify_known_token() {
+        // the HS256 example from jwt.io
+        let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+            eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiaWF0IjoxNTE2MjM5MDIyfQ.\
+            SflKxwRJSMeKKF2QT4fwpMeJf36POk6yJV_adQssw5c";
+        let passphrase = PassphraseSource::default();
+        let decoded = process_jwt_verify(
+            token,
+            "fixtures/jwt_secret.txt",
+            JwtAlgorithm::Hs256,
+            &passphrase,
+            &JwtValidation::default(),
+        )
+        .unwrap();
+        assert_eq!(decoded.claims["name"], "John Doe");
+
+        // the header names HS256, so it must not verify as anything else
+        let res = process_jwt_verify(
+            token,
+            "fixtures/ed25519.pk",
+            JwtAlgorithm::EdDsa,
+            &passphrase,
+            &JwtValidation::default(),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_jwt_validation() {
+        let now = 1_700_000_000;
+        let claims = json!({ "exp": now - 30, "nbf": now - 100, "aud": ["a", "b"], "iss": "me" });
+        let strict = JwtValidation::default();
+        assert!(strict.validate(&claims, now).is_err());
+        let lenient = JwtValidation {
+            leeway: Duration::from_secs(60),
+            aud: Some("b".into()),
+            iss: Some("me".into()),
+        };
+        assert!(lenient.validate(&claims, now).is_ok());
+        let other_aud = JwtValidation {
+            aud: Some("c".into()),
+            ..lenient
+        };
+        assert!(other_aud.validate(&claims, now - 100).is_err());
+        assert!(strict.validate(&json!({ "nbf": now + 10 }), now).is_err());
+        assert!(strict.validate(&json!({ "exp": "soon" }), now).is_err());
+    }
+}
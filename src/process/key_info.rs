@@ -0,0 +1,109 @@
+use ed25519_dalek::VerifyingKey;
+use serde::Serialize;
+use ssh_key::{public::Ed25519PublicKey, Fingerprint, HashAlg};
+
+use super::key_format::{
+    encode_minisign_public, encode_verifying_key, is_public_key_file, key_id_hex,
+};
+use super::text::{Ed25519Signer, Ed25519Verifier};
+use crate::{KeyEncoding, PassphraseSource};
+
+/// What `text key info` shows about an Ed25519 key file
+#[derive(Debug, Serialize)]
+pub struct KeyInfo {
+    pub algorithm: &'static str,
+    pub secret: bool,
+    /// OpenSSH `authorized_keys` form
+    pub public_key: String,
+    pub fingerprint: String,
+    /// the ID written into minisign signature files
+    pub key_id: String,
+    pub randomart: String,
+}
+
+/// Describe a secret or public Ed25519 key. Raw, hex and base64 keys are read
+/// as secret keys unless the file is named `*.pk` or `*.pub`
+pub fn process_text_key_info(key: &str, passphrase: &PassphraseSource) -> anyhow::Result<KeyInfo> {
+    let (public, key_id, secret) = load_public_key(key, passphrase)?;
+    let fingerprint = Fingerprint::new(HashAlg::Sha256, &Ed25519PublicKey::from(&public).into());
+    Ok(KeyInfo {
+        algorithm: "Ed25519",
+        secret,
+        public_key: openssh_public_key(&public)?,
+        fingerprint: fingerprint.to_string(),
+        key_id: key_id_hex(&key_id),
+        randomart: fingerprint.to_randomart("[ED25519 256]"),
+    })
+}
+
+/// The public key and key ID of a secret or public key file, and whether it was a secret key
+pub(crate) fn load_public_key(
+    key: &str,
+    passphrase: &PassphraseSource,
+) -> anyhow::Result<(VerifyingKey, [u8; 8], bool)> {
+    if is_public_key_file(key) {
+        let verifier = Ed25519Verifier::load(key)?;
+        return Ok((verifier.verifying_key(), verifier.key_id(), false));
+    }
+    match Ed25519Signer::load(key, passphrase) {
+        Ok(signer) => Ok((signer.verifying_key(), signer.key_id(), true)),
+        Err(e) => match Ed25519Verifier::load(key) {
+            Ok(verifier) => Ok((verifier.verifying_key(), verifier.key_id(), false)),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+/// The public half of an Ed25519 secret key, minisign keys keep their key ID
+pub fn process_text_key_pub(
+    key: &str,
+    encoding: KeyEncoding,
+    passphrase: &PassphraseSource,
+) -> anyhow::Result<Vec<u8>> {
+    let signer = Ed25519Signer::load(key, passphrase)?;
+    match encoding {
+        KeyEncoding::Minisign => Ok(encode_minisign_public(
+            &signer.verifying_key(),
+            signer.key_id(),
+        )),
+        encoding => encode_verifying_key(&signer.verifying_key(), encoding),
+    }
+}
+
+fn openssh_public_key(key: &VerifyingKey) -> anyhow::Result<String> {
+    let line = encode_verifying_key(key, KeyEncoding::Openssh)?;
+    Ok(String::from_utf8(line)?.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_info() {
+        let passphrase = PassphraseSource::default();
+        let sk = process_text_key_info("fixtures/ed25519.sk", &passphrase).unwrap();
+        let pk = process_text_key_info("fixtures/ed25519.pk", &passphrase).unwrap();
+        assert!(sk.secret);
+        assert!(!pk.secret);
+        assert_eq!(sk.fingerprint, pk.fingerprint);
+        assert_eq!(sk.key_id, pk.key_id);
+        assert!(sk.randomart.starts_with("+--[ED25519 256]--+"));
+
+        // same fingerprint as `ssh-keygen -l` on the OpenSSH pair
+        let ssh = process_text_key_info("fixtures/ed25519_ssh", &passphrase).unwrap();
+        let ssh_pub = process_text_key_info("fixtures/ed25519_ssh.pub", &passphrase).unwrap();
+        assert!(!ssh_pub.secret);
+        assert_eq!(ssh.fingerprint, ssh_pub.fingerprint);
+        assert!(ssh_pub.public_key.starts_with("ssh-ed25519 "));
+    }
+
+    #[test]
+    fn test_key_pub() {
+        let passphrase = PassphraseSource::default();
+        let pk = process_text_key_pub("fixtures/ed25519.sk", KeyEncoding::Raw, &passphrase);
+        assert_eq!(pk.unwrap(), std::fs::read("fixtures/ed25519.pk").unwrap());
+        let ssh_pub = "fixtures/ed25519_ssh.pub";
+        assert!(process_text_key_pub(ssh_pub, KeyEncoding::Raw, &passphrase).is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::key_format::{encode_minisign_public, encode_verifying_key, fingerprint, key_id_hex};
+use super::key_info::load_public_key;
+use super::text::Ed25519Verifier;
+use crate::{create_secret_file, KeyEncoding, PassphraseSource, SignatureFile, TextSignFormat};
+
+const KEYRING_INDEX: &str = "keyring.json";
+
+/// Named Ed25519 keys under `$XDG_DATA_HOME/rcli/keys`. Each key is stored as
+/// `<name>.pk` in minisign format, which keeps its key ID, and secret keys as
+/// `<name>.sk` exactly as they were added, so encrypted keys stay encrypted
+#[derive(Debug)]
+pub struct Keyring {
+    dir: PathBuf,
+    index: KeyringIndex,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyringIndex {
+    default: Option<String>,
+    keys: BTreeMap<String, KeyEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub name: String,
+    pub fingerprint: String,
+    pub key_id: String,
+    pub secret: bool,
+}
+
+impl Keyring {
+    /// `$XDG_DATA_HOME/rcli/keys`, falling back to `~/.local/share/rcli/keys`
+    pub fn default_dir() -> anyhow::Result<PathBuf> {
+        let data_home = match env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => match env::var_os("HOME") {
+                Some(home) => Path::new(&home).join(".local/share"),
+                None => anyhow::bail!("neither XDG_DATA_HOME nor HOME is set"),
+            },
+        };
+        Ok(data_home.join("rcli/keys"))
+    }
+
+    pub fn open_default() -> anyhow::Result<Self> {
+        Self::open(Self::default_dir()?)
+    }
+
+    /// Open a keyring, a missing directory is an empty keyring
+    pub fn open(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        let index = match fs::read_to_string(dir.join(KEYRING_INDEX)) {
+            Ok(index) => serde_json::from_str(&index)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => KeyringIndex::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { dir, index })
+    }
+
+    /// Copy a secret or public key into the keyring, the first key becomes the default
+    pub fn add(
+        &mut self,
+        name: &str,
+        key: &str,
+        passphrase: &PassphraseSource,
+    ) -> anyhow::Result<KeyEntry> {
+        check_name(name)?;
+        if self.index.keys.contains_key(name) {
+            anyhow::bail!("key {} already exists, remove it first", name);
+        }
+        let (public, key_id, secret) = load_public_key(key, passphrase)?;
+        let fingerprint = fingerprint(&public);
+        if let Some(entry) = self.find(&fingerprint) {
+            anyhow::bail!("key {} is already in the keyring as {}", key, entry.name);
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        if secret {
+            let mut file = create_secret_file(self.dir.join(format!("{}.sk", name)))?;
+            file.write_all(&fs::read(key)?)?;
+        }
+        let pk = encode_minisign_public(&public, key_id);
+        fs::write(self.dir.join(format!("{}.pk", name)), pk)?;
+        let entry = KeyEntry {
+            name: name.into(),
+            fingerprint,
+            key_id: key_id_hex(&key_id),
+            secret,
+        };
+        self.index.keys.insert(name.into(), entry.clone());
+        self.index.default.get_or_insert_with(|| name.into());
+        self.save()?;
+        Ok(entry)
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &KeyEntry> {
+        self.index.keys.values()
+    }
+
+    pub fn default_key(&self) -> Option<&KeyEntry> {
+        self.index
+            .default
+            .as_ref()
+            .and_then(|name| self.index.keys.get(name))
+    }
+
+    pub fn set_default(&mut self, spec: &str) -> anyhow::Result<&KeyEntry> {
+        let name = self.get(spec)?.name.clone();
+        self.index.default = Some(name.clone());
+        self.save()?;
+        self.get(&name)
+    }
+
+    pub fn remove(&mut self, spec: &str) -> anyhow::Result<KeyEntry> {
+        let name = self.get(spec)?.name.clone();
+        let entry = self.index.keys.remove(&name).unwrap();
+        for ext in ["sk", "pk"] {
+            let path = self.dir.join(format!("{}.{}", name, ext));
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        if self.index.default.as_deref() == Some(name.as_str()) {
+            self.index.default = None;
+        }
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// The public key in any encoding, or the secret key file as it was added
+    pub fn export(
+        &self,
+        spec: &str,
+        secret: bool,
+        encoding: KeyEncoding,
+    ) -> anyhow::Result<Vec<u8>> {
+        let entry = self.get(spec)?;
+        if secret {
+            return Ok(fs::read(self.secret_path(entry)?)?);
+        }
+        let verifier = Ed25519Verifier::load(self.public_path(entry))?;
+        match encoding {
+            KeyEncoding::Minisign => Ok(encode_minisign_public(
+                &verifier.verifying_key(),
+                verifier.key_id(),
+            )),
+            encoding => encode_verifying_key(&verifier.verifying_key(), encoding),
+        }
+    }
+
+    /// Look a key up by name, fingerprint (with or without `SHA256:`) or key ID
+    pub fn find(&self, spec: &str) -> Option<&KeyEntry> {
+        if let Some(entry) = self.index.keys.get(spec) {
+            return Some(entry);
+        }
+        let fingerprint = spec.strip_prefix("SHA256:").unwrap_or(spec);
+        self.list().find(|entry| {
+            entry.fingerprint.strip_prefix("SHA256:") == Some(fingerprint)
+                || entry.key_id.eq_ignore_ascii_case(spec)
+        })
+    }
+
+    fn get(&self, spec: &str) -> anyhow::Result<&KeyEntry> {
+        self.find(spec)
+            .ok_or_else(|| anyhow::anyhow!("no key named {} in {}", spec, self.dir.display()))
+    }
+
+    pub fn secret_path(&self, entry: &KeyEntry) -> anyhow::Result<PathBuf> {
+        if !entry.secret {
+            anyhow::bail!("key {} has no secret key in the keyring", entry.name);
+        }
+        Ok(self.dir.join(format!("{}.sk", entry.name)))
+    }
+
+    pub fn public_path(&self, entry: &KeyEntry) -> PathBuf {
+        self.dir.join(format!("{}.pk", entry.name))
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let index = serde_json::to_string_pretty(&self.index)?;
+        fs::write(self.dir.join(KEYRING_INDEX), index + "\n")?;
+        Ok(())
+    }
+}
+
+/// A key file path, or a key name, fingerprint or ID in the keyring,
+/// or the default key when no key is given. The keyring is only used for Ed25519 formats
+pub fn resolve_signing_key(key: Option<&str>, format: TextSignFormat) -> anyhow::Result<String> {
+    if let Some(path) = key.filter(|k| *k == "-" || Path::new(k).exists()) {
+        return Ok(path.into());
+    }
+    check_keyring_format(format)?;
+    let keyring = Keyring::open_default()?;
+    let entry = keyring_entry(&keyring, key)?;
+    Ok(keyring.secret_path(entry)?.to_string_lossy().into())
+}
+
+/// Like [`resolve_signing_key`] for the public key. Without a key, the key ID
+/// of a signature file picks the key, otherwise the default key is used.
+/// Signature files are always Ed25519, `format` only matters without one
+pub fn resolve_verifying_key(
+    key: Option<&str>,
+    sig_file: Option<&str>,
+    format: TextSignFormat,
+) -> anyhow::Result<String> {
+    if let Some(path) = key.filter(|k| *k == "-" || Path::new(k).exists()) {
+        return Ok(path.into());
+    }
+    if sig_file.is_none() {
+        check_keyring_format(format)?;
+    }
+    let keyring = Keyring::open_default()?;
+    let entry = match (key, sig_file) {
+        (None, Some(sig_file)) => {
+            let sig: SignatureFile = fs::read_to_string(sig_file)?.parse()?;
+            let key_id = key_id_hex(&sig.key_id);
+            keyring.find(&key_id).ok_or_else(|| {
+                anyhow::anyhow!("no key with ID {} in {}", key_id, keyring.dir.display())
+            })?
+        }
+        _ => keyring_entry(&keyring, key)?,
+    };
+    Ok(keyring.public_path(entry).to_string_lossy().into())
+}
+
+/// Keep a keyring secret from being used as a MAC or ECDSA key
+fn check_keyring_format(format: TextSignFormat) -> anyhow::Result<()> {
+    match format {
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519ph => Ok(()),
+        _ => anyhow::bail!(
+            "keyring keys are Ed25519 only, pass a key file for {}",
+            format
+        ),
+    }
+}
+
+fn keyring_entry<'a>(keyring: &'a Keyring, key: Option<&str>) -> anyhow::Result<&'a KeyEntry> {
+    match key {
+        Some(key) => keyring.find(key).ok_or_else(|| {
+            anyhow::anyhow!("{} is neither a key file nor a key in the keyring", key)
+        }),
+        None => keyring
+            .default_key()
+            .ok_or_else(|| anyhow::anyhow!("no --key given and no default key in the keyring")),
+    }
+}
+
+fn check_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        anyhow::bail!("invalid key name {}: use letters, digits, -, _ and .", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyring() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let passphrase = PassphraseSource::default();
+        let mut keyring = Keyring::open(dir).unwrap();
+        let alice = keyring
+            .add("alice", "fixtures/ed25519.sk", &passphrase)
+            .unwrap();
+        let bob = keyring
+            .add("bob", "fixtures/ed25519_ssh.pub", &passphrase)
+            .unwrap();
+        assert!(alice.secret && !bob.secret);
+        assert!(keyring
+            .add("carol", "fixtures/ed25519.pk", &passphrase)
+            .is_err());
+        assert!(keyring
+            .add("../x", "fixtures/ed25519.sk", &passphrase)
+            .is_err());
+
+        // reopened from disk, found by name, fingerprint or key ID
+        let mut keyring = Keyring::open(dir).unwrap();
+        assert_eq!(keyring.default_key().unwrap().name, "alice");
+        assert_eq!(keyring.find(&bob.fingerprint).unwrap().name, "bob");
+        assert_eq!(keyring.find(&alice.key_id).unwrap().name, "alice");
+        assert_eq!(keyring.set_default("bob").unwrap().name, "bob");
+
+        let pk = keyring.export("alice", false, KeyEncoding::Raw).unwrap();
+        assert_eq!(pk, fs::read("fixtures/ed25519.pk").unwrap());
+        assert!(keyring.export("bob", true, KeyEncoding::Raw).is_err());
+
+        keyring.remove("bob").unwrap();
+        assert!(keyring.default_key().is_none());
+        assert_eq!(keyring.list().count(), 1);
+        assert!(!dir.join("bob.pk").exists());
+    }
+
+    #[test]
+    fn test_resolve_key_format() {
+        // refused before the keyring is opened, a key file is used as given
+        for format in [TextSignFormat::Blake3, TextSignFormat::HmacSha256] {
+            let err = resolve_signing_key(None, format).unwrap_err();
+            assert!(err.to_string().contains("keyring keys are Ed25519 only"));
+            let err = resolve_verifying_key(Some("alice"), None, format).unwrap_err();
+            assert!(err.to_string().contains("keyring keys are Ed25519 only"));
+        }
+        let key = resolve_signing_key(Some("fixtures/blake3.txt"), TextSignFormat::Blake3);
+        assert_eq!(key.unwrap(), "fixtures/blake3.txt");
+    }
+}
@@ -1,11 +1,55 @@
 mod b64;
+mod bao;
 mod csv_convert;
+mod ecdsa;
+mod encrypt;
 mod gen_pass;
+mod hash;
 mod http_serve;
+mod jwt;
+mod key_crypt;
+mod key_format;
+mod key_info;
+mod keyring;
+mod pass_check;
+mod pwned;
+mod shamir;
+mod sig_file;
+mod sshsig;
 mod text;
+mod token;
+mod tree;
+mod x25519;
 
 pub use b64::{process_decode, process_encode};
+pub use bao::{process_bao_decode, process_bao_encode, process_bao_slice, BAO_OUTBOARD_EXT};
 pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use encrypt::{
+    process_text_cipher_generate, process_text_decrypt, process_text_encrypt, CipherKey,
+};
+pub use gen_pass::{
+    process_genpass, process_genpass_batch, process_genpass_output, PasswordMode, PasswordPolicy,
+    PasswordReport,
+};
+pub use hash::{process_hash, process_hash_check, CheckResult, CheckStatus};
 pub use http_serve::process_http_serve;
-pub use text::{process_text_generate, process_text_sign, process_text_verify};
+pub use jwt::{
+    process_jwt_decode, process_jwt_sign, process_jwt_verify, JwtClaims, JwtToken, JwtValidation,
+};
+pub use key_crypt::PassphraseSource;
+pub use key_info::{process_text_key_info, process_text_key_pub, KeyInfo};
+pub use keyring::{resolve_signing_key, resolve_verifying_key, KeyEntry, Keyring};
+pub use pass_check::{process_pass_check, CrackTime, PasswordAnalysis, PatternMatch};
+pub use pwned::PwnedPasswords;
+pub use shamir::{process_text_combine, process_text_split};
+pub use sig_file::{SigAlgorithm, SignatureFile};
+pub use sshsig::{process_text_ssh_sign, process_text_ssh_verify, SshSigners, SshVerifyReport};
+pub use text::{
+    process_text_generate, process_text_sign, process_text_verify, SignOptions, VerifyOptions,
+    VerifyReport,
+};
+pub use token::{process_token_generate, process_token_verify};
+pub use tree::{
+    process_text_sign_tree, process_text_verify_tree, TreeFiles, TreeReport, TREE_MANIFEST,
+};
+pub use x25519::process_text_x25519;
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use super::PwnedPasswords;
+use zxcvbn::{
+    matching::{patterns::MatchPattern, Match},
+    time_estimates::CrackTimeSeconds,
+    zxcvbn,
+};
+
+/// Full zxcvbn analysis of a password
+#[derive(Debug, Serialize)]
+pub struct PasswordAnalysis {
+    pub score: u8,
+    pub guesses: u64,
+    pub guesses_log10: f64,
+    pub crack_times: Vec<CrackTime>,
+    pub patterns: Vec<PatternMatch>,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+    /// times the password was seen in a breach, `None` when no dump was checked
+    pub pwned_count: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrackTime {
+    pub scenario: &'static str,
+    pub seconds: f64,
+    pub display: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PatternMatch {
+    pub pattern: &'static str,
+    pub token: String,
+    pub start: usize,
+    pub end: usize,
+    pub guesses: Option<u64>,
+    pub detail: String,
+}
+
+impl CrackTime {
+    fn new(scenario: &'static str, time: CrackTimeSeconds) -> Self {
+        let seconds = match time {
+            CrackTimeSeconds::Integer(i) => i as f64,
+            CrackTimeSeconds::Float(f) => f,
+        };
+        Self {
+            scenario,
+            seconds,
+            display: time.to_string(),
+        }
+    }
+}
+
+impl From<&Match> for PatternMatch {
+    fn from(m: &Match) -> Self {
+        let (pattern, detail) = match &m.pattern {
+            MatchPattern::Dictionary(p) => (
+                "dictionary",
+                format!(
+                    "{:?} word \"{}\" (rank {}{}{})",
+                    p.dictionary_name,
+                    p.matched_word,
+                    p.rank,
+                    if p.reversed { ", reversed" } else { "" },
+                    if p.l33t { ", l33t" } else { "" }
+                ),
+            ),
+            MatchPattern::Spatial(p) => (
+                "spatial",
+                format!("{} keyboard, {} turns", p.graph, p.turns),
+            ),
+            MatchPattern::Repeat(p) => (
+                "repeat",
+                format!("\"{}\" repeated {} times", p.base_token, p.repeat_count),
+            ),
+            MatchPattern::Sequence(p) => (
+                "sequence",
+                format!(
+                    "{} sequence, {}",
+                    p.sequence_name,
+                    if p.ascending {
+                        "ascending"
+                    } else {
+                        "descending"
+                    }
+                ),
+            ),
+            MatchPattern::Regex(p) => ("regex", p.regex_name.to_string()),
+            MatchPattern::Date(p) => ("date", format!("{:04}-{:02}-{:02}", p.year, p.month, p.day)),
+            MatchPattern::BruteForce => ("bruteforce", String::new()),
+        };
+        Self {
+            pattern,
+            token: m.token.clone(),
+            start: m.i,
+            end: m.j,
+            guesses: m.guesses,
+            detail,
+        }
+    }
+}
+
+/// Analyze an existing password, `user_inputs` (username, email, ...) are
+/// added to the dictionaries so passwords derived from them score low
+pub fn process_pass_check(
+    password: &str,
+    user_inputs: &[String],
+    pwned: Option<&PwnedPasswords>,
+) -> anyhow::Result<PasswordAnalysis> {
+    let user_inputs: Vec<&str> = user_inputs.iter().map(|s| s.as_str()).collect();
+    let estimate = zxcvbn(password, &user_inputs)?;
+    let times = estimate.crack_times();
+    let (warning, suggestions) = match estimate.feedback() {
+        Some(feedback) => (
+            feedback.warning().map(|w| w.to_string()),
+            feedback
+                .suggestions()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        ),
+        None => (None, Vec::new()),
+    };
+
+    Ok(PasswordAnalysis {
+        score: estimate.score(),
+        guesses: estimate.guesses(),
+        guesses_log10: estimate.guesses_log10(),
+        crack_times: vec![
+            CrackTime::new(
+                "online, throttled (100/hour)",
+                times.online_throttling_100_per_hour(),
+            ),
+            CrackTime::new(
+                "online, unthrottled (10/second)",
+                times.online_no_throttling_10_per_second(),
+            ),
+            CrackTime::new(
+                "offline, slow hash (1e4/second)",
+                times.offline_slow_hashing_1e4_per_second(),
+            ),
+            CrackTime::new(
+                "offline, fast hash (1e10/second)",
+                times.offline_fast_hashing_1e10_per_second(),
+            ),
+        ],
+        patterns: estimate.sequence().iter().map(Into::into).collect(),
+        warning,
+        suggestions,
+        pwned_count: pwned.map(|p| p.lookup(password)).transpose()?,
+    })
+}
+
+impl fmt::Display for PasswordAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "score: {}/4", self.score)?;
+        writeln!(
+            f,
+            "guesses: {} (10^{:.2})",
+            self.guesses, self.guesses_log10
+        )?;
+        writeln!(f, "crack times:")?;
+        for time in &self.crack_times {
+            writeln!(f, "  {}: {}", time.scenario, time.display)?;
+        }
+        writeln!(f, "patterns:")?;
+        for m in &self.patterns {
+            write!(
+                f,
+                "  [{}..={}] {} \"{}\"",
+                m.start, m.end, m.pattern, m.token
+            )?;
+            if !m.detail.is_empty() {
+                write!(f, ": {}", m.detail)?;
+            }
+            writeln!(f)?;
+        }
+        match self.pwned_count {
+            Some(0) => writeln!(f, "breached: no")?,
+            Some(count) => writeln!(f, "breached: yes, seen {} times", count)?,
+            None => {}
+        }
+        if let Some(warning) = &self.warning {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        if !self.suggestions.is_empty() {
+            writeln!(f, "suggestions:")?;
+            for suggestion in &self.suggestions {
+                writeln!(f, "  - {}", suggestion)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_check_user_inputs() {
+        let analysis = process_pass_check("alice2024!", &[], None).unwrap();
+        let hinted = process_pass_check("alice2024!", &["alice".into()], None).unwrap();
+        assert!(hinted.guesses < analysis.guesses);
+        assert!(hinted
+            .patterns
+            .iter()
+            .any(|m| m.pattern == "dictionary" && m.detail.contains("UserInputs")));
+        assert_eq!(hinted.crack_times.len(), 4);
+    }
+
+    #[test]
+    fn test_pass_check_blank_password() {
+        assert!(process_pass_check("", &[], None).is_err());
+    }
+
+    #[test]
+    fn test_pass_check_pwned() {
+        let pwned = PwnedPasswords::open("fixtures/pwned.txt").unwrap();
+        let analysis = process_pass_check("hunter2", &[], Some(&pwned)).unwrap();
+        assert_eq!(analysis.pwned_count, Some(551));
+    }
+}
@@ -0,0 +1,91 @@
+use std::{cmp::Ordering, fs::File, path::Path};
+
+use memmap2::Mmap;
+use sha1::{Digest, Sha1};
+
+/// A local Pwned Passwords dump: one `SHA1:count` line per password,
+/// sorted by hash, as downloaded from haveibeenpwned.com
+pub struct PwnedPasswords {
+    data: Option<Mmap>,
+}
+
+impl PwnedPasswords {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        // mapping an empty file fails on some platforms
+        let data = if file.metadata()?.len() == 0 {
+            None
+        } else {
+            // SAFETY: the dump is only read, a concurrent writer truncating it is not supported
+            Some(unsafe { Mmap::map(&file)? })
+        };
+        Ok(Self { data })
+    }
+
+    /// Number of times the password appears in the dump, 0 if it does not
+    pub fn lookup(&self, password: &str) -> anyhow::Result<u64> {
+        let hash = hex_upper(&Sha1::digest(password.as_bytes()));
+        let data = match &self.data {
+            Some(data) => &data[..],
+            None => return Ok(0),
+        };
+
+        // binary search over byte offsets, snapping each probe to the start of its line
+        let (mut lo, mut hi) = (0, data.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let start = data[lo..mid]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(lo, |p| lo + p + 1);
+            let end = data[mid..hi]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(hi, |p| mid + p);
+            let line = trim_line(&data[start..end]);
+
+            let (line_hash, count) = match line.iter().position(|&b| b == b':') {
+                Some(p) => (&line[..p], &line[p + 1..]),
+                None => anyhow::bail!("malformed line in pwned passwords file at byte {}", start),
+            };
+            match cmp_hash(line_hash, hash.as_bytes()) {
+                Ordering::Equal => {
+                    let count = std::str::from_utf8(count)?.trim().parse()?;
+                    return Ok(count);
+                }
+                Ordering::Less => lo = end + 1,
+                Ordering::Greater => hi = start,
+            }
+        }
+        Ok(0)
+    }
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn cmp_hash(line_hash: &[u8], hash: &[u8]) -> Ordering {
+    line_hash
+        .iter()
+        .map(u8::to_ascii_uppercase)
+        .cmp(hash.iter().copied())
+}
+
+fn hex_upper(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pwned_lookup() {
+        let pwned = PwnedPasswords::open("fixtures/pwned.txt").unwrap();
+        assert_eq!(pwned.lookup("password").unwrap(), 3);
+        assert_eq!(pwned.lookup("trustno1").unwrap(), 1236);
+        assert_eq!(pwned.lookup("correct horse battery staple").unwrap(), 688);
+        assert_eq!(pwned.lookup("not in the dump").unwrap(), 0);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bip39::Language;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::{CliError, ShareEncoding};
+
+const SHARE_VERSION: u8 = 1;
+/// version, secret id, threshold, x coordinate, secret digest
+const SHARE_HEADER_LEN: usize = 1 + 4 + 1 + 1 + 4;
+const CHECKSUM_LEN: usize = 4;
+
+/// One Shamir share of a secret: the secret bytes are split one by one
+/// over GF(256), `y` holds this share's point for every byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Share {
+    /// random, the same for all shares of one split
+    id: [u8; 4],
+    threshold: u8,
+    x: u8,
+    /// truncated SHA-256 of id and secret, checks the combined secret
+    digest: [u8; 4],
+    y: Vec<u8>,
+}
+
+/// Split a secret into `shares` shares, any `threshold` of which recover it
+pub fn process_text_split(
+    secret: &[u8],
+    shares: u8,
+    threshold: u8,
+    encoding: ShareEncoding,
+) -> anyhow::Result<Vec<String>> {
+    if threshold < 2 || threshold > shares {
+        anyhow::bail!("threshold must be between 2 and the number of shares");
+    }
+    if secret.is_empty() {
+        anyhow::bail!("the secret is empty");
+    }
+    let mut id = [0u8; 4];
+    OsRng.fill_bytes(&mut id);
+    let digest = secret_digest(&id, secret);
+
+    let mut ys = vec![Vec::with_capacity(secret.len()); shares as usize];
+    let mut coefficients = vec![0u8; threshold as usize];
+    for &byte in secret {
+        // a random polynomial of degree threshold - 1 through (0, byte)
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+        for (i, y) in ys.iter_mut().enumerate() {
+            y.push(evaluate(&coefficients, i as u8 + 1));
+        }
+    }
+    Ok(ys
+        .into_iter()
+        .enumerate()
+        .map(|(i, y)| {
+            let share = Share {
+                id,
+                threshold,
+                x: i as u8 + 1,
+                digest,
+                y,
+            };
+            encode_share(&share.to_bytes(), encoding)
+        })
+        .collect())
+}
+
+/// Recover a secret from at least `threshold` shares in either encoding
+pub fn process_text_combine(shares: &[String]) -> anyhow::Result<Vec<u8>> {
+    let mut points = BTreeMap::new();
+    let mut first: Option<Share> = None;
+    for share in shares {
+        let share = Share::from_bytes(&decode_share(share)?)?;
+        if let Some(first) = &first {
+            if share.id != first.id {
+                anyhow::bail!(
+                    "share {} belongs to secret {}, not {}",
+                    share.x,
+                    hex::encode(share.id),
+                    hex::encode(first.id)
+                );
+            }
+            if share.threshold != first.threshold
+                || share.digest != first.digest
+                || share.y.len() != first.y.len()
+            {
+                anyhow::bail!("share {} does not match the other shares", share.x);
+            }
+        }
+        points.insert(share.x, share.y.clone());
+        first.get_or_insert(share);
+    }
+    let Some(first) = first else {
+        anyhow::bail!("no shares given");
+    };
+    if points.len() < first.threshold as usize {
+        anyhow::bail!(
+            "secret {} needs {} shares, got {}",
+            hex::encode(first.id),
+            first.threshold,
+            points.len()
+        );
+    }
+
+    let points: Vec<_> = points.into_iter().take(first.threshold as usize).collect();
+    let secret: Vec<u8> = (0..first.y.len())
+        .map(|i| interpolate(points.iter().map(|(x, y)| (*x, y[i]))))
+        .collect();
+    if secret_digest(&first.id, &secret) != first.digest {
+        return Err(anyhow::Error::from(CliError::VerificationFailed)
+            .context("the shares do not reconstruct the secret"));
+    }
+    Ok(secret)
+}
+
+impl Share {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SHARE_HEADER_LEN + self.y.len() + CHECKSUM_LEN);
+        data.push(SHARE_VERSION);
+        data.extend_from_slice(&self.id);
+        data.push(self.threshold);
+        data.push(self.x);
+        data.extend_from_slice(&self.digest);
+        data.extend_from_slice(&self.y);
+        let checksum = checksum(&data);
+        data.extend_from_slice(&checksum);
+        data
+    }
+
+    fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() <= SHARE_HEADER_LEN + CHECKSUM_LEN {
+            anyhow::bail!("share is too short");
+        }
+        if !has_valid_checksum(data) {
+            anyhow::bail!("share checksum mismatch, it was mistyped or damaged");
+        }
+        let data = &data[..data.len() - CHECKSUM_LEN];
+        if data[0] != SHARE_VERSION {
+            anyhow::bail!("unsupported share version {}", data[0]);
+        }
+        Ok(Self {
+            id: data[1..5].try_into()?,
+            threshold: data[5],
+            x: data[6],
+            digest: data[7..11].try_into()?,
+            y: data[SHARE_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+fn has_valid_checksum(data: &[u8]) -> bool {
+    data.len() > CHECKSUM_LEN && {
+        let (data, sum) = data.split_at(data.len() - CHECKSUM_LEN);
+        checksum(data)[..] == sum[..]
+    }
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    Sha256::digest(data)[..CHECKSUM_LEN].try_into().unwrap()
+}
+
+fn secret_digest(id: &[u8; 4], secret: &[u8]) -> [u8; 4] {
+    let digest = Sha256::new()
+        .chain_update(id)
+        .chain_update(secret)
+        .finalize();
+    digest[..4].try_into().unwrap()
+}
+
+fn encode_share(data: &[u8], encoding: ShareEncoding) -> String {
+    match encoding {
+        ShareEncoding::Base64 => URL_SAFE_NO_PAD.encode(data),
+        ShareEncoding::Mnemonic => {
+            // 11 bits per BIP-39 word, the last word is padded with zero bits
+            let words = Language::English.word_list();
+            let bits = data.len() * 8;
+            (0..bits.div_ceil(11))
+                .map(|w| {
+                    let index = (0..11).fold(0usize, |acc, b| {
+                        let bit = w * 11 + b;
+                        let set = bit < bits && data[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                        acc << 1 | set as usize
+                    });
+                    words[index]
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+    }
+}
+
+/// Base64, or BIP-39 words when the share contains spaces
+fn decode_share(share: &str) -> anyhow::Result<Vec<u8>> {
+    let share = share.trim();
+    if !share.contains(char::is_whitespace) {
+        return URL_SAFE_NO_PAD
+            .decode(share)
+            .map_err(|e| anyhow::anyhow!("invalid share: {}", e));
+    }
+    let mut data = vec![];
+    let (mut acc, mut nbits) = (0u32, 0);
+    let words = share.split_whitespace().count();
+    for word in share.split_whitespace() {
+        let index = Language::English
+            .find_word(&word.to_lowercase())
+            .ok_or_else(|| anyhow::anyhow!("invalid share: {} is not a BIP-39 word", word))?;
+        acc = acc << 11 | index as u32;
+        nbits += 11;
+        while nbits >= 8 {
+            nbits -= 8;
+            data.push((acc >> nbits) as u8);
+        }
+        acc &= (1 << nbits) - 1;
+    }
+    if acc != 0 {
+        anyhow::bail!("invalid share: the last word has trailing bits set");
+    }
+    // with 8 or more padding bits a zero byte too many was decoded,
+    // one share length in 2^32 is ambiguous and the checksum settles it
+    let shorter_fits = (data.len() - 1) * 8 > (words - 1) * 11;
+    if shorter_fits && data.last() == Some(&0) && !has_valid_checksum(&data) {
+        data.pop();
+    }
+    Ok(data)
+}
+
+/// The polynomial at `x`, by Horner's rule
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0, |acc, &c| gf_mul(acc, x) ^ c)
+}
+
+/// Lagrange interpolation at x = 0; addition and subtraction are both xor
+fn interpolate(points: impl Iterator<Item = (u8, u8)> + Clone) -> u8 {
+    points.clone().fold(0, |secret, (xi, yi)| {
+        let (num, den) = points
+            .clone()
+            .filter(|&(xj, _)| xj != xi)
+            .fold((1, 1), |(num, den), (xj, _)| {
+                (gf_mul(num, xj), gf_mul(den, xj ^ xi))
+            });
+        secret ^ gf_mul(yi, gf_mul(num, gf_inv(den)))
+    })
+}
+
+/// Multiplication in GF(2^8) with the AES polynomial x^8 + x^4 + x^3 + x + 1,
+/// without table lookups or branches on secret data
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = a << 1 ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// a^254 = a^-1 for a != 0
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf256() {
+        // FIPS-197 section 4.2
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_split_combine() {
+        let secret = std::fs::read("fixtures/ed25519.sk").unwrap();
+        for encoding in [ShareEncoding::Base64, ShareEncoding::Mnemonic] {
+            let shares = process_text_split(&secret, 5, 3, encoding).unwrap();
+            assert_eq!(shares.len(), 5);
+            let picked = [shares[4].clone(), shares[0].clone(), shares[2].clone()];
+            for subset in [&shares[..3], &shares[2..], &picked[..]] {
+                assert_eq!(process_text_combine(subset).unwrap(), secret);
+            }
+            assert!(process_text_combine(&shares[..2]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_combine_rejects_bad_shares() {
+        let shares = process_text_split(b"secret", 3, 2, ShareEncoding::Base64).unwrap();
+        let other = process_text_split(b"secret", 3, 2, ShareEncoding::Base64).unwrap();
+        // shares of another split of the same secret
+        let err = process_text_combine(&[shares[0].clone(), other[1].clone()]).unwrap_err();
+        assert!(err.to_string().contains("belongs to secret"));
+
+        // a mistyped share fails its checksum
+        let mut typo = shares[1].clone().into_bytes();
+        typo[10] = if typo[10] == b'A' { b'B' } else { b'A' };
+        let typo = String::from_utf8(typo).unwrap();
+        let err = process_text_combine(&[shares[0].clone(), typo]).unwrap_err();
+        assert!(err.to_string().contains("checksum"));
+
+        let words = process_text_split(b"secret", 3, 2, ShareEncoding::Mnemonic).unwrap();
+        let one = words[0].replacen(' ', " zoo ", 1);
+        assert!(process_text_combine(&[one, words[1].clone()]).is_err());
+    }
+}
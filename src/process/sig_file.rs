@@ -0,0 +1,219 @@
+use std::{fmt, io::Read, str::FromStr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use super::key_format::key_id_hex;
+use crate::CliError;
+
+const UNTRUSTED_PREFIX: &str = "untrusted comment: ";
+const TRUSTED_PREFIX: &str = "trusted comment: ";
+const DEFAULT_UNTRUSTED_COMMENT: &str = "signature from rcli secret key";
+
+/// Signature algorithms of minisign
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigAlgorithm {
+    /// `Ed`, pure Ed25519 over the message
+    Ed25519,
+    /// `ED`, Ed25519 over the BLAKE2b-512 hash of the message, so it streams
+    Ed25519Blake2b,
+}
+
+/// A detached signature file, byte-compatible with minisign:
+/// the global signature covers the signature and the trusted comment
+#[derive(Debug)]
+pub struct SignatureFile {
+    pub algorithm: SigAlgorithm,
+    pub key_id: [u8; 8],
+    pub signature: Signature,
+    pub untrusted_comment: String,
+    pub trusted_comment: String,
+    pub global_signature: Signature,
+}
+
+impl SigAlgorithm {
+    fn tag(&self) -> &'static [u8; 2] {
+        match self {
+            SigAlgorithm::Ed25519 => b"Ed",
+            SigAlgorithm::Ed25519Blake2b => b"ED",
+        }
+    }
+
+    fn from_tag(tag: &[u8]) -> anyhow::Result<Self> {
+        match tag {
+            b"Ed" => Ok(SigAlgorithm::Ed25519),
+            b"ED" => Ok(SigAlgorithm::Ed25519Blake2b),
+            v => anyhow::bail!(
+                "Unsupported signature algorithm: {:?}",
+                String::from_utf8_lossy(v)
+            ),
+        }
+    }
+
+    /// The bytes the message signature is computed over
+    fn message(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
+        match self {
+            SigAlgorithm::Ed25519 => {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+            SigAlgorithm::Ed25519Blake2b => {
+                let mut hasher = Blake2b512::new();
+                std::io::copy(reader, &mut hasher)?;
+                Ok(hasher.finalize().to_vec())
+            }
+        }
+    }
+}
+
+impl From<SigAlgorithm> for &'static str {
+    fn from(value: SigAlgorithm) -> Self {
+        match value {
+            SigAlgorithm::Ed25519 => "ed25519",
+            SigAlgorithm::Ed25519Blake2b => "ed25519-blake2b",
+        }
+    }
+}
+
+impl SignatureFile {
+    pub(crate) fn sign(
+        key: &SigningKey,
+        key_id: [u8; 8],
+        algorithm: SigAlgorithm,
+        reader: &mut dyn Read,
+        trusted_comment: String,
+    ) -> anyhow::Result<Self> {
+        if trusted_comment.contains(['\r', '\n']) {
+            anyhow::bail!("trusted comment must be a single line");
+        }
+        let signature = key.sign(&algorithm.message(reader)?);
+        let global_signature = key.sign(&global_message(&signature, &trusted_comment));
+        Ok(Self {
+            algorithm,
+            key_id,
+            signature,
+            untrusted_comment: DEFAULT_UNTRUSTED_COMMENT.to_string(),
+            trusted_comment,
+            global_signature,
+        })
+    }
+
+    /// Check both the message and the trusted comment, a key ID mismatch is an error
+    pub(crate) fn verify(
+        &self,
+        key: &VerifyingKey,
+        key_id: [u8; 8],
+        reader: &mut dyn Read,
+    ) -> anyhow::Result<bool> {
+        if self.key_id != key_id {
+            let err = anyhow::anyhow!(
+                "signature key id {} does not match public key id {}",
+                key_id_hex(&self.key_id),
+                key_id_hex(&key_id)
+            );
+            return Err(CliError::InvalidKey(err).into());
+        }
+        let message = self.algorithm.message(reader)?;
+        let global = global_message(&self.signature, &self.trusted_comment);
+        Ok(key.verify(&message, &self.signature).is_ok()
+            && key.verify(&global, &self.global_signature).is_ok())
+    }
+}
+
+fn global_message(signature: &Signature, trusted_comment: &str) -> Vec<u8> {
+    [&signature.to_bytes()[..], trusted_comment.as_bytes()].concat()
+}
+
+impl fmt::Display for SignatureFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sig = [
+            &self.algorithm.tag()[..],
+            &self.key_id,
+            &self.signature.to_bytes(),
+        ]
+        .concat();
+        writeln!(f, "{}{}", UNTRUSTED_PREFIX, self.untrusted_comment)?;
+        writeln!(f, "{}", STANDARD.encode(sig))?;
+        writeln!(f, "{}{}", TRUSTED_PREFIX, self.trusted_comment)?;
+        writeln!(f, "{}", STANDARD.encode(self.global_signature.to_bytes()))
+    }
+}
+
+impl FromStr for SignatureFile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut lines = s.lines().map(|l| l.trim_end_matches('\r'));
+        let mut next = |what: &str| {
+            lines
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("signature file is missing the {}", what))
+        };
+        let untrusted_comment = next("untrusted comment")?
+            .strip_prefix(UNTRUSTED_PREFIX)
+            .ok_or_else(|| anyhow::anyhow!("signature file must start with an untrusted comment"))?
+            .to_string();
+        let sig = STANDARD.decode(next("signature")?.trim())?;
+        let trusted_comment = next("trusted comment")?
+            .strip_prefix(TRUSTED_PREFIX)
+            .ok_or_else(|| anyhow::anyhow!("signature file has no trusted comment"))?
+            .to_string();
+        let global_signature = STANDARD.decode(next("global signature")?.trim())?;
+
+        if sig.len() != 2 + 8 + 64 {
+            anyhow::bail!("invalid signature length: {}", sig.len());
+        }
+        Ok(Self {
+            algorithm: SigAlgorithm::from_tag(&sig[..2])?,
+            key_id: sig[2..10].try_into()?,
+            signature: Signature::from_slice(&sig[10..])?,
+            untrusted_comment,
+            trusted_comment,
+            global_signature: Signature::from_slice(&global_signature)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_signature_file_round_trip() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let pk = sk.verifying_key();
+        let key_id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let data = b"hello world!";
+        for algorithm in [SigAlgorithm::Ed25519, SigAlgorithm::Ed25519Blake2b] {
+            let sig =
+                SignatureFile::sign(&sk, key_id, algorithm, &mut &data[..], "timestamp:1".into())
+                    .unwrap();
+            let sig: SignatureFile = sig.to_string().parse().unwrap();
+            assert_eq!(sig.algorithm, algorithm);
+            assert!(sig.verify(&pk, key_id, &mut &data[..]).unwrap());
+            assert!(!sig.verify(&pk, key_id, &mut &b"hello world?"[..]).unwrap());
+            assert!(sig.verify(&pk, [0; 8], &mut &data[..]).is_err());
+
+            // the trusted comment is covered by the global signature
+            let mut forged = sig;
+            forged.trusted_comment = "timestamp:2".into();
+            assert!(!forged.verify(&pk, key_id, &mut &data[..]).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_signature_file_rejects_multiline_comment() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let res = SignatureFile::sign(
+            &sk,
+            [0; 8],
+            SigAlgorithm::Ed25519,
+            &mut &b""[..],
+            "a\nb".into(),
+        );
+        assert!(res.is_err());
+    }
+}
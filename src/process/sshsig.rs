@@ -0,0 +1,398 @@
+use std::{
+    fs,
+    io::{self, Read},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use ssh_key::{
+    public::{Ed25519PublicKey, KeyData},
+    Algorithm, HashAlg, LineEnding, PublicKey, SshSig,
+};
+
+use super::key_format::{fingerprint, load_verifying_key};
+use super::text::{Ed25519Signer, TextSign};
+use crate::{get_reader, CliError, PassphraseSource};
+
+/// The result of `text ssh-verify`
+#[derive(Debug, Serialize)]
+pub struct SshVerifyReport {
+    pub namespace: String,
+    pub key_fingerprint: String,
+    /// allowed_signers principals the key is trusted for in this namespace
+    pub principals: Vec<String>,
+    pub valid: bool,
+}
+
+/// Who may sign: an `allowed_signers` file or a single public key
+pub enum SshSigners<'a> {
+    AllowedSigners {
+        path: &'a str,
+        principal: Option<&'a str>,
+    },
+    Key(&'a str),
+}
+
+/// One line of an `allowed_signers` file, see ssh-keygen(1)
+#[derive(Debug)]
+struct AllowedSigner {
+    /// a pattern list such as `*@example.com,!bot@example.com`
+    principals: String,
+    /// a pattern list of namespaces, any namespace when unset
+    namespaces: Option<String>,
+    valid_after: Option<SystemTime>,
+    valid_before: Option<SystemTime>,
+    key: VerifyingKey,
+}
+
+/// Sign the input like `ssh-keygen -Y sign`, returning the armored signature.
+/// The message is hashed with SHA-512 as it is streamed
+pub fn process_text_ssh_sign(
+    input: &str,
+    key: &str,
+    namespace: &str,
+    passphrase: &PassphraseSource,
+) -> anyhow::Result<String> {
+    if namespace.is_empty() {
+        anyhow::bail!("the namespace must not be empty");
+    }
+    let signer = Ed25519Signer::load(key, passphrase)?;
+    let digest = hash_reader(&mut get_reader(input)?, HashAlg::Sha512)?;
+    let signed_data = signed_data(namespace, HashAlg::Sha512, &digest);
+    let sig = signer.sign(&mut signed_data.as_slice())?;
+
+    let public = KeyData::from(Ed25519PublicKey::from(&signer.verifying_key()));
+    let signature = ssh_key::Signature::new(Algorithm::Ed25519, sig)?;
+    let sshsig = SshSig::new(public, namespace, HashAlg::Sha512, signature)?;
+    Ok(sshsig.to_pem(LineEnding::LF)?)
+}
+
+/// Verify an SSH signature like `ssh-keygen -Y verify`: the namespace must match
+/// and the signing key must be trusted for it by the allowed_signers file
+pub fn process_text_ssh_verify(
+    input: &str,
+    sig_file: &str,
+    namespace: &str,
+    signers: SshSigners,
+) -> anyhow::Result<SshVerifyReport> {
+    let sig = SshSig::from_pem(fs::read(sig_file)?)
+        .map_err(|e| CliError::InvalidSignature(anyhow::anyhow!("invalid SSH signature: {}", e)))?;
+    let key = match sig.public_key() {
+        KeyData::Ed25519(key) => VerifyingKey::try_from(key)?,
+        key => anyhow::bail!("unsupported SSH signature key type: {}", key.algorithm()),
+    };
+    let mut report = SshVerifyReport {
+        namespace: sig.namespace().into(),
+        key_fingerprint: fingerprint(&key),
+        principals: vec![],
+        valid: false,
+    };
+    if sig.namespace() != namespace {
+        return Ok(report);
+    }
+
+    let digest = hash_reader(&mut get_reader(input)?, sig.hash_alg())?;
+    let signed_data = signed_data(namespace, sig.hash_alg(), &digest);
+    let signature = Signature::from_slice(sig.signature_bytes())
+        .map_err(|e| CliError::InvalidSignature(e.into()))?;
+    if key.verify(&signed_data, &signature).is_err() {
+        return Ok(report);
+    }
+
+    report.valid = match signers {
+        SshSigners::Key(path) => load_verifying_key(&fs::read(path)?)? == key,
+        SshSigners::AllowedSigners { path, principal } => {
+            let now = SystemTime::now();
+            for signer in parse_allowed_signers(&fs::read_to_string(path)?)? {
+                if signer.key == key && signer.allows(namespace, now) {
+                    report.principals.push(signer.principals);
+                }
+            }
+            match principal {
+                Some(principal) => {
+                    let allowed = report
+                        .principals
+                        .iter()
+                        .any(|p| match_pattern_list(p, principal));
+                    report.principals = vec![principal.into()];
+                    allowed
+                }
+                None => !report.principals.is_empty(),
+            }
+        }
+    };
+    if !report.valid {
+        report.principals.clear();
+    }
+    Ok(report)
+}
+
+impl AllowedSigner {
+    fn allows(&self, namespace: &str, now: SystemTime) -> bool {
+        let namespace_ok = match &self.namespaces {
+            Some(namespaces) => match_pattern_list(namespaces, namespace),
+            None => true,
+        };
+        namespace_ok
+            && self.valid_after.is_none_or(|t| now >= t)
+            && self.valid_before.is_none_or(|t| now <= t)
+    }
+}
+
+/// The blob that is actually signed, as defined in OpenSSH's PROTOCOL.sshsig
+fn signed_data(namespace: &str, hash_alg: HashAlg, digest: &[u8]) -> Vec<u8> {
+    let mut data = b"SSHSIG".to_vec();
+    for field in [
+        namespace.as_bytes(),
+        b"",
+        hash_alg.as_str().as_bytes(),
+        digest,
+    ] {
+        data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        data.extend_from_slice(field);
+    }
+    data
+}
+
+fn hash_reader(reader: &mut dyn Read, hash_alg: HashAlg) -> anyhow::Result<Vec<u8>> {
+    match hash_alg {
+        HashAlg::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(reader, &mut hasher)?;
+            Ok(hasher.finalize().to_vec())
+        }
+        HashAlg::Sha512 => {
+            let mut hasher = Sha512::new();
+            io::copy(reader, &mut hasher)?;
+            Ok(hasher.finalize().to_vec())
+        }
+        alg => anyhow::bail!("unsupported SSH signature hash: {}", alg),
+    }
+}
+
+/// Parse `principals [options] keytype base64-key [comment]` lines. Only Ed25519
+/// keys are kept, and cert-authority lines are skipped as certificates are not supported
+fn parse_allowed_signers(text: &str) -> anyhow::Result<Vec<AllowedSigner>> {
+    let mut signers = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || anyhow::anyhow!("invalid allowed_signers line {}: {}", n + 1, line);
+        let (principals, mut rest) = split_field(line).ok_or_else(invalid)?;
+        let mut options = vec![];
+        if PublicKey::from_openssh(rest).is_err() {
+            let (opts, r) = split_field(rest).ok_or_else(invalid)?;
+            options = split_options(opts);
+            rest = r;
+        }
+        let key = PublicKey::from_openssh(rest).map_err(|_| invalid())?;
+        let Some(key) = key.key_data().ed25519() else {
+            continue;
+        };
+        let mut signer = AllowedSigner {
+            principals: principals.into(),
+            namespaces: None,
+            valid_after: None,
+            valid_before: None,
+            key: key.try_into()?,
+        };
+        let mut cert_authority = false;
+        for option in options {
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.trim_matches('"'))),
+                None => (option, None),
+            };
+            match (name.to_ascii_lowercase().as_str(), value) {
+                ("cert-authority", None) => cert_authority = true,
+                ("namespaces", Some(v)) => signer.namespaces = Some(v.into()),
+                ("valid-after", Some(v)) => {
+                    signer.valid_after = Some(parse_time(v).ok_or_else(invalid)?)
+                }
+                ("valid-before", Some(v)) => {
+                    signer.valid_before = Some(parse_time(v).ok_or_else(invalid)?)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        if !cert_authority {
+            signers.push(signer);
+        }
+    }
+    Ok(signers)
+}
+
+/// The first whitespace-separated field, which may be double-quoted, and the rest of the line
+fn split_field(line: &str) -> Option<(&str, &str)> {
+    let end = match line.strip_prefix('"') {
+        Some(quoted) => quoted.find('"')? + 2,
+        None => line.find(char::is_whitespace)?,
+    };
+    let (field, rest) = line.split_at(end);
+    Some((field.trim_matches('"'), rest.trim_start()))
+}
+
+/// Comma-separated options, commas inside quoted values do not split
+fn split_options(options: &str) -> Vec<&str> {
+    let mut out = vec![];
+    let (mut start, mut quoted) = (0, false);
+    for (i, c) in options.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                out.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&options[start..]);
+    out
+}
+
+/// OpenSSH pattern lists: any pattern may match, a matching `!pattern` rejects
+fn match_pattern_list(patterns: &str, s: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split(',') {
+        match pattern.strip_prefix('!') {
+            Some(pattern) if glob_match(pattern, s) => return false,
+            Some(_) => {}
+            None => matched |= glob_match(pattern, s),
+        }
+    }
+    matched
+}
+
+/// `*` and `?` wildcards
+fn glob_match(pattern: &str, s: &str) -> bool {
+    match pattern.chars().next() {
+        None => s.is_empty(),
+        Some('*') => (0..=s.len())
+            .filter(|&i| s.is_char_boundary(i))
+            .any(|i| glob_match(&pattern[1..], &s[i..])),
+        Some(c) => {
+            let Some(first) = s.chars().next() else {
+                return false;
+            };
+            (c == '?' || c == first) && glob_match(&pattern[c.len_utf8()..], &s[first.len_utf8()..])
+        }
+    }
+}
+
+/// `YYYYMMDD[HHMM[SS]]` with an optional `Z`, read as UTC
+fn parse_time(time: &str) -> Option<SystemTime> {
+    let time = time.strip_suffix(['Z', 'z']).unwrap_or(time);
+    if !matches!(time.len(), 8 | 12 | 14) || !time.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        time.get(range).map_or(Some(0), |f| f.parse().ok())
+    };
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // days since the epoch of a proleptic Gregorian date
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let secs = u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALLOWED_SIGNERS: &str = "fixtures/allowed_signers";
+
+    fn signers<'a>(principal: Option<&'a str>) -> SshSigners<'a> {
+        SshSigners::AllowedSigners {
+            path: ALLOWED_SIGNERS,
+            principal,
+        }
+    }
+
+    #[test]
+    fn test_ssh_sign_verify() {
+        let passphrase = PassphraseSource::default();
+        let input = "fixtures/b64.txt";
+        let sig = process_text_ssh_sign(input, "fixtures/ed25519_ssh", "file", &passphrase);
+        let dir = tempfile::tempdir().unwrap();
+        let sig_file = dir.path().join("b64.txt.sig");
+        fs::write(&sig_file, sig.unwrap()).unwrap();
+        let sig_file = sig_file.to_str().unwrap();
+
+        let report = process_text_ssh_verify(input, sig_file, "file", signers(None)).unwrap();
+        assert!(report.valid);
+        assert_eq!(report.principals, ["rcli@example.com"]);
+        let key = SshSigners::Key("fixtures/ed25519_ssh.pub");
+        assert!(
+            process_text_ssh_verify(input, sig_file, "file", key)
+                .unwrap()
+                .valid
+        );
+
+        // wrong namespace, principal or message
+        let report = process_text_ssh_verify(input, sig_file, "git", signers(None)).unwrap();
+        assert!(!report.valid);
+        let other = signers(Some("someone@example.com"));
+        assert!(
+            !process_text_ssh_verify(input, sig_file, "file", other)
+                .unwrap()
+                .valid
+        );
+        let other = "fixtures/blake3.txt";
+        assert!(
+            !process_text_ssh_verify(other, sig_file, "file", signers(None))
+                .unwrap()
+                .valid
+        );
+    }
+
+    #[test]
+    fn test_verify_ssh_keygen_signature() {
+        // ssh-keygen -Y sign -n file -f fixtures/ed25519_ssh fixtures/b64.txt
+        let sig_file = "fixtures/b64.txt.sshsig";
+        let principal = signers(Some("rcli@example.com"));
+        let report = process_text_ssh_verify("fixtures/b64.txt", sig_file, "file", principal);
+        assert!(report.unwrap().valid);
+    }
+
+    #[test]
+    fn test_allowed_signers() {
+        let signers = parse_allowed_signers(&fs::read_to_string(ALLOWED_SIGNERS).unwrap()).unwrap();
+        // the cert-authority line is skipped
+        assert_eq!(signers.len(), 2);
+        let now = SystemTime::now();
+        assert!(signers[0].allows("file", now) && !signers[0].allows("git", now));
+        assert!(!signers[1].allows("git", now));
+
+        assert!(match_pattern_list("*@example.com,!bad@*", "a@example.com"));
+        assert!(!match_pattern_list(
+            "*@example.com,!bad@*",
+            "bad@example.com"
+        ));
+        assert!(!match_pattern_list("a?c", "abbc"));
+        assert_eq!(
+            parse_time("19700102Z"),
+            Some(UNIX_EPOCH + Duration::from_secs(86400))
+        );
+        assert_eq!(
+            parse_time("20240229123000"),
+            Some(UNIX_EPOCH + Duration::from_secs(1709209800))
+        );
+        assert!(parse_time("2024").is_none());
+    }
+}
@@ -1,12 +1,31 @@
-use std::{collections::HashMap, fs, io::Read, path::Path};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use base64::{
+    engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD},
+    Engine,
+};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
-use rand::rngs::OsRng;
+use hmac::{digest::KeyInit, Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use sha3::Sha3_256;
 
-use crate::{get_reader, TextSignFormat};
-
-use super::gen_pass;
+use super::ecdsa::{EcdsaCurve, EcdsaSigner, EcdsaVerifier, P256Key, Secp256k1Key};
+use super::key_crypt::{encrypt_key, read_key};
+use super::key_format::{
+    decode_key, encode_key, encode_signing_key, encode_verifying_key, fingerprint, key_id,
+    key_id_hex, load_signing_key, load_verifying_key, minisign_key_id,
+};
+use super::sig_file::{SigAlgorithm, SignatureFile};
+use super::x25519::X25519Key;
+use crate::{get_reader, CliError, KeyEncoding, KeyType, PassphraseSource, TextSignFormat};
 
 pub trait TextSign {
     /// Sign the data from the reader and return the signature
@@ -19,7 +38,32 @@ pub trait TextVerify {
 }
 
 pub trait TextKeyGenerator {
-    fn generate() -> anyhow::Result<HashMap<&'static str, Vec<u8>>>;
+    /// Generate a new key, encoded with `encoding`, keyed by file name
+    fn generate(encoding: KeyEncoding) -> anyhow::Result<HashMap<&'static str, Vec<u8>>>;
+}
+
+/// How [`process_text_sign`] outputs the signature
+#[derive(Debug, Default)]
+pub struct SignOptions<'a> {
+    /// write a minisign signature file instead of printing the signature
+    pub sig_file: Option<&'a str>,
+    pub trusted_comment: Option<&'a str>,
+    /// print hex instead of base64
+    pub hex: bool,
+    /// encode ECDSA signatures as ASN.1 DER instead of raw r || s
+    pub der: bool,
+}
+
+/// Where [`process_text_verify`] finds the signature and how strictly it reads it
+#[derive(Debug, Default)]
+pub struct VerifyOptions<'a> {
+    pub sig: Option<&'a str>,
+    /// a minisign signature file, used instead of `sig`
+    pub sig_file: Option<&'a str>,
+    /// `sig` is hex instead of base64
+    pub hex: bool,
+    /// accept malleable high-S secp256k1 signatures
+    pub allow_high_s: bool,
 }
 
 pub struct Blake3 {
@@ -28,29 +72,29 @@ pub struct Blake3 {
 
 impl TextSign for Blake3 {
     fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
-        // TODO: improve perf by reading in chunks
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let hash = blake3::keyed_hash(&self.key, &buf);
+        let hash = blake3::Hasher::new_keyed(&self.key)
+            .update_reader(reader)?
+            .finalize();
         Ok(hash.as_bytes().to_vec())
     }
 }
 
 impl TextVerify for Blake3 {
-    fn verify(&self, mut reader: impl Read, sig: &[u8]) -> anyhow::Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let hash = blake3::keyed_hash(&self.key, &buf);
-        Ok(hash.as_bytes() == sig)
+    fn verify(&self, reader: impl Read, sig: &[u8]) -> anyhow::Result<bool> {
+        let hash = blake3::Hasher::new_keyed(&self.key)
+            .update_reader(reader)?
+            .finalize();
+        // blake3::Hash compares in constant time
+        Ok(<[u8; 32]>::try_from(sig).is_ok_and(|sig| hash == blake3::Hash::from(sig)))
     }
 }
 
 impl TextKeyGenerator for Blake3 {
-    fn generate() -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
-        let key = gen_pass::process_genpass(32, true, true, true, true)?;
-        let key = key.as_bytes().to_vec();
+    fn generate(encoding: KeyEncoding) -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
         let mut map = HashMap::new();
-        map.insert("blake3.txt", key);
+        map.insert("blake3.txt", encode_key(&key, encoding)?);
         Ok(map)
     }
 }
@@ -60,133 +104,515 @@ impl Blake3 {
         Self { key }
     }
     fn try_new(key: &[u8]) -> anyhow::Result<Self> {
-        let key = &key[..32];
-        let key = key.try_into()?;
+        let key = decode_key(key).map_err(CliError::InvalidKey)?;
         Ok(Self::new(key))
     }
-    fn load(key: impl AsRef<Path>) -> anyhow::Result<Self> {
-        let key = fs::read(key)?;
+    fn load(key: impl AsRef<Path>, passphrase: &PassphraseSource) -> anyhow::Result<Self> {
+        let key = read_key(key, passphrase)?;
         Self::try_new(&key)
     }
+
+    fn fingerprint(&self) -> String {
+        secret_fingerprint(&self.key)
+    }
+}
+
+/// HMAC with SHA-256, SHA-512 or SHA3-256, as used to sign webhooks
+pub(crate) struct HmacKey {
+    key: Vec<u8>,
+    hash: HmacHash,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HmacHash {
+    Sha256,
+    Sha512,
+    Sha3_256,
+}
+
+impl TextSign for HmacKey {
+    fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
+        let tag = match self.hash {
+            HmacHash::Sha256 => mac_reader::<Hmac<Sha256>>(&self.key, reader)?
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            HmacHash::Sha512 => mac_reader::<Hmac<Sha512>>(&self.key, reader)?
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            HmacHash::Sha3_256 => mac_reader::<Hmac<Sha3_256>>(&self.key, reader)?
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+        };
+        Ok(tag)
+    }
 }
 
-struct Ed25519Signer {
+impl TextVerify for HmacKey {
+    fn verify(&self, mut reader: impl Read, sig: &[u8]) -> anyhow::Result<bool> {
+        // verify_slice compares in constant time
+        let valid = match self.hash {
+            HmacHash::Sha256 => mac_reader::<Hmac<Sha256>>(&self.key, &mut reader)?
+                .verify_slice(sig)
+                .is_ok(),
+            HmacHash::Sha512 => mac_reader::<Hmac<Sha512>>(&self.key, &mut reader)?
+                .verify_slice(sig)
+                .is_ok(),
+            HmacHash::Sha3_256 => mac_reader::<Hmac<Sha3_256>>(&self.key, &mut reader)?
+                .verify_slice(sig)
+                .is_ok(),
+        };
+        Ok(valid)
+    }
+}
+
+impl TextKeyGenerator for HmacKey {
+    fn generate(encoding: KeyEncoding) -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
+        // as long as the largest output, so it suits every hash
+        let mut key = [0u8; 64];
+        OsRng.fill_bytes(&mut key);
+        let mut map = HashMap::new();
+        map.insert("hmac.txt", encode_key(&key, encoding)?);
+        Ok(map)
+    }
+}
+
+impl HmacKey {
+    /// Webhook secrets are used verbatim as text, so hex or base64 keys are not decoded;
+    /// only the line break an editor or `echo` leaves at the end of a text key is dropped
+    fn try_new(key: &[u8], hash: HmacHash) -> anyhow::Result<Self> {
+        let key = match std::str::from_utf8(key) {
+            Ok(text) => text.trim_end_matches(['\r', '\n']).as_bytes(),
+            Err(_) => key,
+        };
+        if key.is_empty() {
+            return Err(CliError::InvalidKey(anyhow::anyhow!("HMAC key is empty")).into());
+        }
+        Ok(Self {
+            key: key.to_vec(),
+            hash,
+        })
+    }
+
+    pub(crate) fn load(
+        key: impl AsRef<Path>,
+        hash: HmacHash,
+        passphrase: &PassphraseSource,
+    ) -> anyhow::Result<Self> {
+        let key = read_key(key, passphrase)?;
+        Self::try_new(&key, hash)
+    }
+
+    fn fingerprint(&self) -> String {
+        secret_fingerprint(&self.key)
+    }
+}
+
+impl TryFrom<TextSignFormat> for HmacHash {
+    type Error = anyhow::Error;
+
+    fn try_from(format: TextSignFormat) -> anyhow::Result<Self> {
+        match format {
+            TextSignFormat::HmacSha256 => Ok(HmacHash::Sha256),
+            TextSignFormat::HmacSha512 => Ok(HmacHash::Sha512),
+            TextSignFormat::HmacSha3_256 => Ok(HmacHash::Sha3_256),
+            v => anyhow::bail!("{} is not an HMAC format", v),
+        }
+    }
+}
+
+impl TryFrom<TextSignFormat> for EcdsaCurve {
+    type Error = anyhow::Error;
+
+    fn try_from(format: TextSignFormat) -> anyhow::Result<Self> {
+        match format {
+            TextSignFormat::P256 => Ok(EcdsaCurve::P256),
+            TextSignFormat::Secp256k1 => Ok(EcdsaCurve::Secp256k1),
+            v => anyhow::bail!("{} is not an ECDSA format", v),
+        }
+    }
+}
+
+fn mac_reader<M: Mac + KeyInit>(key: &[u8], reader: &mut dyn Read) -> anyhow::Result<M> {
+    let mut mac = <M as Mac>::new_from_slice(key)?;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(mac);
+        }
+        mac.update(&buf[..n]);
+    }
+}
+
+/// Signs with pure Ed25519 by default, which needs the whole message in memory,
+/// or with Ed25519ph (RFC 8032, SHA-512 prehash) which streams it
+pub(crate) struct Ed25519Signer {
     key: SigningKey,
+    key_id: [u8; 8],
+    prehash: bool,
 }
 
 impl TextSign for Ed25519Signer {
     fn sign(&self, reader: &mut dyn Read) -> anyhow::Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let sig = self.key.sign(&buf).to_bytes().to_vec();
-        Ok(sig)
+        let sig = if self.prehash {
+            self.key.sign_prehashed(sha512_reader(reader)?, None)?
+        } else {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            self.key.sign(&buf)
+        };
+        Ok(sig.to_bytes().to_vec())
     }
 }
 
 impl Ed25519Signer {
     fn new(key: SigningKey) -> Self {
-        Self { key }
+        Self {
+            key_id: key_id(&key.verifying_key()),
+            key,
+            prehash: false,
+        }
+    }
+
+    fn prehashed(self) -> Self {
+        Self {
+            prehash: true,
+            ..self
+        }
     }
     fn try_new(key: &[u8]) -> anyhow::Result<Self> {
-        let key = SigningKey::from_bytes(key.try_into()?);
-        Ok(Self::new(key))
+        let signer = Self::new(load_signing_key(key).map_err(CliError::InvalidKey)?);
+        match minisign_key_id(key) {
+            Some(key_id) => Ok(Self { key_id, ..signer }),
+            None => Ok(signer),
+        }
     }
 
-    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
-        let key = fs::read(path)?;
+    pub(crate) fn load(
+        path: impl AsRef<Path>,
+        passphrase: &PassphraseSource,
+    ) -> anyhow::Result<Self> {
+        let key = read_key(path, passphrase)?;
         Self::try_new(&key)
     }
+
+    pub(crate) fn verifying_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    pub(crate) fn key_id(&self) -> [u8; 8] {
+        self.key_id
+    }
+
+    /// Sign into a minisign signature file, prehashing with BLAKE2b rather than SHA-512
+    fn sign_file(
+        &self,
+        reader: &mut dyn Read,
+        trusted_comment: String,
+    ) -> anyhow::Result<SignatureFile> {
+        let algorithm = if self.prehash {
+            SigAlgorithm::Ed25519Blake2b
+        } else {
+            SigAlgorithm::Ed25519
+        };
+        SignatureFile::sign(&self.key, self.key_id, algorithm, reader, trusted_comment)
+    }
 }
 
 impl TextKeyGenerator for Ed25519Signer {
-    fn generate() -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
+    fn generate(encoding: KeyEncoding) -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
         let mut csrng = OsRng;
         let sk = SigningKey::generate(&mut csrng);
-        let pk = sk.verifying_key().as_bytes().to_vec();
-        let sk = sk.as_bytes().to_vec();
+        let pk = encode_verifying_key(&sk.verifying_key(), encoding)?;
+        let sk = encode_signing_key(&sk, encoding)?;
         let mut map = HashMap::new();
-        println!("sk: {:?}, pk: {:?}", sk, pk);
         map.insert("ed25519.sk", sk);
         map.insert("ed25519.pk", pk);
         Ok(map)
     }
 }
 
-struct Ed25519Verifier {
+pub(crate) struct Ed25519Verifier {
     key: VerifyingKey,
+    key_id: [u8; 8],
+    prehash: bool,
 }
 
 impl TextVerify for Ed25519Verifier {
     fn verify(&self, mut reader: impl Read, sig: &[u8]) -> anyhow::Result<bool> {
+        let sig = Signature::from_slice(sig).map_err(|_| {
+            CliError::InvalidSignature(anyhow::anyhow!(
+                "invalid signature: expected 64 bytes, got {}",
+                sig.len()
+            ))
+        })?;
+        if self.prehash {
+            let digest = sha512_reader(&mut reader)?;
+            return Ok(self.key.verify_prehashed(digest, None, &sig).is_ok());
+        }
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf)?;
-        let sig = Signature::from_bytes(sig.try_into()?);
         Ok(self.key.verify(&buf, &sig).is_ok())
     }
 }
 
 impl Ed25519Verifier {
     fn new(key: VerifyingKey) -> Self {
-        Self { key }
+        Self {
+            key_id: key_id(&key),
+            key,
+            prehash: false,
+        }
+    }
+
+    fn prehashed(self) -> Self {
+        Self {
+            prehash: true,
+            ..self
+        }
     }
 
     fn try_new(key: &[u8]) -> anyhow::Result<Self> {
-        let key = VerifyingKey::from_bytes(key.try_into()?)?;
-        Ok(Self::new(key))
+        let verifier = Self::new(load_verifying_key(key).map_err(CliError::InvalidKey)?);
+        match minisign_key_id(key) {
+            Some(key_id) => Ok(Self { key_id, ..verifier }),
+            None => Ok(verifier),
+        }
     }
-    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+    pub(crate) fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         let key = fs::read(&path)?;
         Self::try_new(&key)
     }
+
+    pub(crate) fn verifying_key(&self) -> VerifyingKey {
+        self.key
+    }
+
+    pub(crate) fn key_id(&self) -> [u8; 8] {
+        self.key_id
+    }
+
+    /// The signature file names its own algorithm, so `prehash` is ignored
+    fn verify_file(&self, reader: &mut dyn Read, sig: &SignatureFile) -> anyhow::Result<bool> {
+        sig.verify(&self.key, self.key_id, reader)
+    }
+}
+
+/// Secret keys are fingerprinted by their hash, which reveals nothing about them
+fn secret_fingerprint(key: &[u8]) -> String {
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(key)))
 }
 
-pub fn process_text_sign(input: &str, key: &str, format: TextSignFormat) -> anyhow::Result<()> {
+fn sha512_reader(reader: &mut dyn Read) -> io::Result<Sha512> {
+    let mut hasher = Sha512::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher)
+}
+
+/// Sign the input, printing the bare signature or, with `sig_file`,
+/// writing a minisign-compatible signature file
+pub fn process_text_sign(
+    input: &str,
+    key: &str,
+    format: TextSignFormat,
+    passphrase: &PassphraseSource,
+    options: &SignOptions,
+) -> anyhow::Result<()> {
     let mut reader = get_reader(input)?;
+    if let Some(sig_file) = options.sig_file {
+        let comment = options.trusted_comment;
+        let sig = sign_to_file(&mut reader, input, key, format, passphrase, comment)?;
+        fs::write(sig_file, sig.to_string())?;
+        return Ok(());
+    }
+
+    let signed = sign_reader(&mut reader, key, format, passphrase, options.der)?;
+    let signed = if options.hex {
+        hex::encode(signed)
+    } else {
+        URL_SAFE_NO_PAD.encode(signed)
+    };
+    println!("{}", signed);
+    Ok(())
+}
+
+/// Sign into a minisign signature file, `input` names the file in the default trusted comment
+pub(crate) fn sign_to_file(
+    reader: &mut dyn Read,
+    input: &str,
+    key: &str,
+    format: TextSignFormat,
+    passphrase: &PassphraseSource,
+    trusted_comment: Option<&str>,
+) -> anyhow::Result<SignatureFile> {
+    let signer = match format {
+        TextSignFormat::Ed25519 => Ed25519Signer::load(key, passphrase)?,
+        TextSignFormat::Ed25519ph => Ed25519Signer::load(key, passphrase)?.prehashed(),
+        _ => anyhow::bail!("signature files need an Ed25519 key"),
+    };
+    let trusted_comment = match trusted_comment {
+        Some(comment) => comment.to_string(),
+        None => default_trusted_comment(input, signer.prehash)?,
+    };
+    signer.sign_file(reader, trusted_comment)
+}
+
+/// The bare signature of the reader
+pub(crate) fn sign_reader(
+    reader: &mut dyn Read,
+    key: &str,
+    format: TextSignFormat,
+    passphrase: &PassphraseSource,
+    der: bool,
+) -> anyhow::Result<Vec<u8>> {
     let signed = match format {
         TextSignFormat::Blake3 => {
-            let signer = Blake3::load(key)?;
-            signer.sign(&mut reader)?
+            let signer = Blake3::load(key, passphrase)?;
+            signer.sign(reader)?
         }
         TextSignFormat::Ed25519 => {
-            let signer = Ed25519Signer::load(key)?;
-            signer.sign(&mut reader)?
+            let signer = Ed25519Signer::load(key, passphrase)?;
+            signer.sign(reader)?
+        }
+        TextSignFormat::Ed25519ph => {
+            let signer = Ed25519Signer::load(key, passphrase)?.prehashed();
+            signer.sign(reader)?
+        }
+        TextSignFormat::HmacSha256 | TextSignFormat::HmacSha512 | TextSignFormat::HmacSha3_256 => {
+            let signer = HmacKey::load(key, format.try_into()?, passphrase)?;
+            signer.sign(reader)?
+        }
+        TextSignFormat::P256 | TextSignFormat::Secp256k1 => {
+            let signer = EcdsaSigner::load(key, format.try_into()?, passphrase)?.der(der);
+            signer.sign(reader)?
         }
     };
-    let signed = URL_SAFE_NO_PAD.encode(signed);
-    println!("{}", signed);
-    Ok(())
+    Ok(signed)
+}
+
+/// Same trusted comment as minisign: timestamp, file name and whether it was prehashed
+fn default_trusted_comment(input: &str, prehash: bool) -> anyhow::Result<String> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let file = match Path::new(input).file_name() {
+        Some(name) if input != "-" => name.to_string_lossy(),
+        _ => "-".into(),
+    };
+    let mut comment = format!("timestamp:{}\tfile:{}", timestamp, file);
+    if prehash {
+        comment.push_str("\thashed");
+    }
+    Ok(comment)
+}
+
+/// Outcome of a verification, printed as is with `--json`
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub algorithm: &'static str,
+    pub key_fingerprint: String,
+    /// minisign key ID, only for signature files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trusted_comment: Option<String>,
+    pub valid: bool,
 }
 
 pub fn process_text_verify(
     input: &str,
     key: &str,
-    sig: &str,
     format: TextSignFormat,
-) -> anyhow::Result<()> {
-    let reader = get_reader(input)?;
+    passphrase: &PassphraseSource,
+    options: &VerifyOptions,
+) -> anyhow::Result<VerifyReport> {
+    let mut reader = get_reader(input)?;
+    if let Some(sig_file) = options.sig_file {
+        let sig: SignatureFile = fs::read_to_string(sig_file)?
+            .parse()
+            .map_err(CliError::InvalidSignature)?;
+        let verifier = Ed25519Verifier::load(key)?;
+        let valid = verifier.verify_file(&mut reader, &sig)?;
+        return Ok(VerifyReport {
+            algorithm: sig.algorithm.into(),
+            key_fingerprint: fingerprint(&verifier.key),
+            key_id: Some(key_id_hex(&sig.key_id)),
+            trusted_comment: Some(sig.trusted_comment),
+            valid,
+        });
+    }
 
-    let sig = URL_SAFE_NO_PAD.decode(sig)?;
-    let verified = match format {
+    let sig = match options.sig {
+        Some(sig) if options.hex => hex::decode(sig).map_err(|e| e.to_string()),
+        Some(sig) => URL_SAFE_NO_PAD.decode(sig).map_err(|e| e.to_string()),
+        None => anyhow::bail!("either a signature or a signature file is required"),
+    }
+    .map_err(|e| CliError::InvalidSignature(anyhow::anyhow!("invalid signature: {}", e)))?;
+    let (valid, key_fingerprint) = match format {
         TextSignFormat::Blake3 => {
-            let verifier = Blake3::load(key)?;
-            verifier.verify(reader, &sig)?
+            let verifier = Blake3::load(key, passphrase)?;
+            (verifier.verify(reader, &sig)?, verifier.fingerprint())
         }
         TextSignFormat::Ed25519 => {
             let verifier = Ed25519Verifier::load(key)?;
-            verifier.verify(reader, &sig)?
+            (verifier.verify(reader, &sig)?, fingerprint(&verifier.key))
+        }
+        TextSignFormat::Ed25519ph => {
+            let verifier = Ed25519Verifier::load(key)?.prehashed();
+            (verifier.verify(reader, &sig)?, fingerprint(&verifier.key))
+        }
+        TextSignFormat::HmacSha256 | TextSignFormat::HmacSha512 | TextSignFormat::HmacSha3_256 => {
+            let verifier = HmacKey::load(key, format.try_into()?, passphrase)?;
+            (verifier.verify(reader, &sig)?, verifier.fingerprint())
+        }
+        TextSignFormat::P256 | TextSignFormat::Secp256k1 => {
+            let verifier =
+                EcdsaVerifier::load(key, format.try_into()?)?.allow_high_s(options.allow_high_s);
+            (verifier.verify(reader, &sig)?, verifier.fingerprint())
         }
     };
-    println!("verify result {}", verified);
-    Ok(())
+    Ok(VerifyReport {
+        algorithm: format.into(),
+        key_fingerprint,
+        key_id: None,
+        trusted_comment: None,
+        valid,
+    })
 }
 
+/// Generate a key, secret keys are encrypted when a passphrase source is given
 pub fn process_text_generate(
-    format: TextSignFormat,
+    key_type: KeyType,
+    encoding: KeyEncoding,
+    passphrase: Option<&PassphraseSource>,
 ) -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
-    match format {
-        TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::Ed25519 => Ed25519Signer::generate(),
+    let mut keys = match key_type {
+        KeyType::Blake3 => Blake3::generate(encoding),
+        KeyType::Ed25519 => Ed25519Signer::generate(encoding),
+        KeyType::Hmac => HmacKey::generate(encoding),
+        KeyType::P256 => P256Key::generate(encoding),
+        KeyType::Secp256k1 => Secp256k1Key::generate(encoding),
+        KeyType::X25519 => X25519Key::generate(encoding),
+    }?;
+    if let Some(passphrase) = passphrase {
+        encrypt_secret_keys(&mut keys, passphrase)?;
     }
+    Ok(keys)
+}
+
+/// Encrypt every generated key except the public halves
+pub(crate) fn encrypt_secret_keys(
+    keys: &mut HashMap<&'static str, Vec<u8>>,
+    passphrase: &PassphraseSource,
+) -> anyhow::Result<()> {
+    let passphrase = passphrase.read_new()?;
+    for (name, key) in keys.iter_mut() {
+        if !name.ends_with(".pk") {
+            *key = encrypt_key(key, &passphrase)?;
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -195,19 +621,113 @@ mod tests {
 
     #[test]
     fn test_blake3_sign_verify() {
-        let obj = Blake3::load("fixtures/blake3.txt").unwrap();
+        let obj = Blake3::load("fixtures/blake3.txt", &PassphraseSource::default()).unwrap();
         let data = b"hello world!";
         let sig = obj.sign(&mut &data[..]).unwrap();
         assert!(obj.verify(&data[..], &sig).unwrap())
     }
 
+    #[test]
+    fn test_blake3_generate_encodings() {
+        for encoding in [KeyEncoding::Raw, KeyEncoding::Hex, KeyEncoding::Base64] {
+            let keys = process_text_generate(KeyType::Blake3, encoding, None).unwrap();
+            let key = Blake3::try_new(&keys["blake3.txt"]).unwrap();
+            let data = b"hello world!";
+            let sig = key.sign(&mut &data[..]).unwrap();
+            assert!(key.verify(&data[..], &sig).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_blake3_load_invalid_key() {
+        assert!(Blake3::try_new(b"too short").is_err());
+        assert!(Blake3::try_new(&[0u8; 33]).is_err());
+    }
+
+    #[test]
+    fn test_verify_report() {
+        let passphrase = PassphraseSource::default();
+        let key = Blake3::load("fixtures/blake3.txt", &passphrase).unwrap();
+        let sig = key
+            .sign(&mut fs::File::open("fixtures/b64.txt").unwrap())
+            .unwrap();
+        let sig = URL_SAFE_NO_PAD.encode(sig);
+        let options = VerifyOptions {
+            sig: Some(&sig),
+            ..Default::default()
+        };
+        let report = process_text_verify(
+            "fixtures/b64.txt",
+            "fixtures/blake3.txt",
+            TextSignFormat::Blake3,
+            &passphrase,
+            &options,
+        )
+        .unwrap();
+        assert!(report.valid);
+        assert_eq!(report.algorithm, "blake3");
+
+        let options = VerifyOptions {
+            sig: Some("AAAA"),
+            ..Default::default()
+        };
+        let err = process_text_verify(
+            "fixtures/b64.txt",
+            "fixtures/ed25519.pk",
+            TextSignFormat::Ed25519,
+            &passphrase,
+            &options,
+        )
+        .unwrap_err();
+        assert_eq!(crate::exit_code(&err), crate::EXIT_INVALID_SIGNATURE);
+    }
+
+    #[test]
+    fn test_hmac_sign_verify() {
+        // RFC 4231 test case 2
+        let key = HmacKey::try_new(b"Jefe\n", HmacHash::Sha256).unwrap();
+        let data = b"what do ya want for nothing?";
+        let sig = key.sign(&mut &data[..]).unwrap();
+        assert_eq!(
+            hex::encode(&sig),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(key.verify(&data[..], &sig).unwrap());
+        assert!(!key.verify(&data[..], &sig[..31]).unwrap());
+
+        for hash in [HmacHash::Sha512, HmacHash::Sha3_256] {
+            let key = HmacKey::try_new(b"Jefe", hash).unwrap();
+            let sig = key.sign(&mut &data[..]).unwrap();
+            assert!(key.verify(&data[..], &sig).unwrap());
+            assert!(!key
+                .verify(&b"what do ya want for something?"[..], &sig)
+                .unwrap());
+        }
+    }
+
     #[test]
     fn text_ed25519_sign_verify() {
-        let sk = Ed25519Signer::load("fixtures/ed25519.sk").unwrap();
+        let sk = Ed25519Signer::load("fixtures/ed25519.sk", &PassphraseSource::default()).unwrap();
+        let pk = Ed25519Verifier::load("fixtures/ed25519.pk").unwrap();
+
+        let data = b"hello world!";
+        let sig = sk.sign(&mut &data[..]).unwrap();
+        assert!(pk.verify(&data[..], &sig).unwrap());
+    }
+
+    #[test]
+    fn text_ed25519ph_sign_verify() {
+        let sk = Ed25519Signer::load("fixtures/ed25519.sk", &PassphraseSource::default())
+            .unwrap()
+            .prehashed();
         let pk = Ed25519Verifier::load("fixtures/ed25519.pk").unwrap();
 
         let data = b"hello world!";
         let sig = sk.sign(&mut &data[..]).unwrap();
+        // a prehashed signature does not verify as a pure one
+        assert!(!pk.verify(&data[..], &sig).unwrap());
+        let pk = pk.prehashed();
         assert!(pk.verify(&data[..], &sig).unwrap());
+        assert!(!pk.verify(&b"hello world?"[..], &sig).unwrap());
     }
 }
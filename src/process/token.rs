@@ -0,0 +1,114 @@
+use rand::{rngs::OsRng, RngCore};
+
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// 62^6 > 2^32, so six chars always hold the crc32
+const CHECKSUM_WIDTH: usize = 6;
+pub const MIN_TOKEN_BYTES: usize = 16;
+pub const MAX_TOKEN_BYTES: usize = 64;
+
+/// Generate a `prefix_<base62 random><base62 crc32>` token. The checksum covers
+/// `prefix_<random>`, so secret scanners can tell real tokens from look-alikes offline
+pub fn process_token_generate(prefix: &str, bytes: usize) -> anyhow::Result<String> {
+    verify_prefix(prefix)?;
+    if !(MIN_TOKEN_BYTES..=MAX_TOKEN_BYTES).contains(&bytes) {
+        anyhow::bail!(
+            "token entropy must be between {} and {} bytes",
+            MIN_TOKEN_BYTES,
+            MAX_TOKEN_BYTES
+        );
+    }
+
+    let mut random = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut random);
+    let body = format!("{}_{}", prefix, base62_encode(&random, base62_width(bytes)));
+    let checksum = crc32fast::hash(body.as_bytes());
+    Ok(format!(
+        "{}{}",
+        body,
+        base62_encode(&checksum.to_be_bytes(), CHECKSUM_WIDTH)
+    ))
+}
+
+/// Check the embedded checksum of a token, malformed tokens are an error
+pub fn process_token_verify(token: &str) -> anyhow::Result<bool> {
+    let (prefix, rest) = token
+        .rsplit_once('_')
+        .ok_or_else(|| anyhow::anyhow!("token has no prefix"))?;
+    verify_prefix(prefix)?;
+    if let Some(c) = rest.chars().find(|c| !c.is_ascii_alphanumeric()) {
+        anyhow::bail!("token contains invalid character {:?}", c);
+    }
+    if rest.len() <= CHECKSUM_WIDTH {
+        anyhow::bail!("token is too short");
+    }
+
+    let (body, checksum) = token.split_at(token.len() - CHECKSUM_WIDTH);
+    let expected = crc32fast::hash(body.as_bytes());
+    Ok(base62_encode(&expected.to_be_bytes(), CHECKSUM_WIDTH) == checksum)
+}
+
+fn verify_prefix(prefix: &str) -> anyhow::Result<()> {
+    if prefix.is_empty()
+        || !prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        anyhow::bail!("token prefix must be non-empty ASCII letters, digits or '_'");
+    }
+    Ok(())
+}
+
+/// Number of base62 digits needed for `bytes` bytes
+fn base62_width(bytes: usize) -> usize {
+    (bytes as f64 * 8.0 / 62f64.log2()).ceil() as usize
+}
+
+/// Encode a big-endian number as exactly `width` base62 digits
+fn base62_encode(bytes: &[u8], width: usize) -> String {
+    let mut num = bytes.to_vec();
+    let mut digits = Vec::with_capacity(width);
+    for _ in 0..width {
+        let mut rem = 0u32;
+        for b in num.iter_mut() {
+            let acc = (rem << 8) | *b as u32;
+            *b = (acc / 62) as u8;
+            rem = acc % 62;
+        }
+        digits.push(BASE62[rem as usize]);
+    }
+    digits.iter().rev().map(|&d| d as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base62_encode() {
+        assert_eq!(base62_encode(&[0, 61], 3), "00z");
+        assert_eq!(base62_encode(&[0, 62], 3), "010");
+        assert_eq!(
+            base62_encode(&u32::MAX.to_be_bytes(), CHECKSUM_WIDTH),
+            "4gfFC3"
+        );
+    }
+
+    #[test]
+    fn test_token_generate_verify() {
+        let token = process_token_generate("rcli_test", 32).unwrap();
+        assert!(token.starts_with("rcli_test_"));
+        assert_eq!(token.len(), "rcli_test_".len() + 43 + CHECKSUM_WIDTH);
+        assert!(process_token_verify(&token).unwrap());
+
+        // flip one char of the random part
+        let mut tampered = token.into_bytes();
+        let i = "rcli_test_".len();
+        tampered[i] = if tampered[i] == b'a' { b'b' } else { b'a' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert!(!process_token_verify(&tampered).unwrap());
+
+        assert!(process_token_verify("no-prefix").is_err());
+        assert!(process_token_generate("bad-prefix", 32).is_err());
+        assert!(process_token_generate("rcli", 8).is_err());
+    }
+}
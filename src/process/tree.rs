@@ -0,0 +1,285 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    path::{self, Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rayon::prelude::*;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use super::hash::{format_checksum_line, parse_checksum_line};
+use super::text::{process_text_verify, sign_reader, sign_to_file, VerifyOptions, VerifyReport};
+use crate::{PassphraseSource, TextSignFormat};
+
+/// Default manifest name, written into the signed directory with a `.sig` next to it
+pub const TREE_MANIFEST: &str = "B3SUMS";
+
+/// Where the manifest and its signature live, relative paths are inside the directory
+#[derive(Debug)]
+pub struct TreeFiles {
+    pub manifest: PathBuf,
+    pub signature: PathBuf,
+}
+
+/// Differences between a directory and its manifest
+#[derive(Debug, Serialize)]
+pub struct TreeReport {
+    pub signature: VerifyReport,
+    pub missing: Vec<String>,
+    pub extra: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl TreeFiles {
+    pub fn new(dir: &Path, manifest: Option<&Path>, signature: Option<&Path>) -> Self {
+        let manifest = dir.join(manifest.unwrap_or(Path::new(TREE_MANIFEST)));
+        let signature = match signature {
+            Some(signature) => dir.join(signature),
+            // next to the manifest, so --manifest alone moves both
+            None => manifest.with_file_name(format!(
+                "{}.sig",
+                manifest.file_name().unwrap_or_default().to_string_lossy()
+            )),
+        };
+        Self {
+            manifest,
+            signature,
+        }
+    }
+}
+
+impl TreeReport {
+    pub fn is_valid(&self) -> bool {
+        self.signature.valid
+            && self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+/// Hash every file under `dir` with blake3 in parallel and write a sorted `b3sum` manifest,
+/// then sign it: Ed25519 keys write a minisign signature file, the others the bare signature
+pub fn process_text_sign_tree(
+    dir: &Path,
+    files: &TreeFiles,
+    key: &str,
+    format: TextSignFormat,
+    passphrase: &PassphraseSource,
+) -> anyhow::Result<usize> {
+    let hashes = hash_tree(dir, files)?;
+    fs::write(&files.manifest, format_manifest(&hashes))?;
+
+    let mut reader = File::open(&files.manifest)?;
+    let signature = match format {
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519ph => {
+            let input = files.manifest.to_string_lossy();
+            sign_to_file(&mut reader, &input, key, format, passphrase, None)?.to_string()
+        }
+        _ => {
+            let sig = sign_reader(&mut reader, key, format, passphrase, false)?;
+            format!("{}\n", URL_SAFE_NO_PAD.encode(sig))
+        }
+    };
+    fs::write(&files.signature, signature)?;
+    Ok(hashes.len())
+}
+
+/// Check the manifest signature, then compare the manifest against the directory
+pub fn process_text_verify_tree(
+    dir: &Path,
+    files: &TreeFiles,
+    key: &str,
+    format: TextSignFormat,
+    passphrase: &PassphraseSource,
+) -> anyhow::Result<TreeReport> {
+    let manifest = files.manifest.to_string_lossy();
+    let sig = fs::read_to_string(&files.signature)?;
+    let signature = if sig.starts_with("untrusted comment:") {
+        let sig_file = files.signature.to_string_lossy();
+        let options = VerifyOptions {
+            sig_file: Some(&sig_file),
+            ..Default::default()
+        };
+        process_text_verify(&manifest, key, format, passphrase, &options)?
+    } else {
+        let options = VerifyOptions {
+            sig: Some(sig.trim()),
+            ..Default::default()
+        };
+        process_text_verify(&manifest, key, format, passphrase, &options)?
+    };
+    let mut report = TreeReport {
+        signature,
+        missing: vec![],
+        extra: vec![],
+        modified: vec![],
+    };
+    // an unsigned manifest says nothing about the files
+    if !report.signature.valid {
+        return Ok(report);
+    }
+
+    let mut expected = parse_manifest(&fs::read_to_string(&files.manifest)?)?;
+    for (path, hash) in hash_tree(dir, files)? {
+        match expected.remove(&path) {
+            Some(h) if h == hash.to_hex().as_str() => {}
+            Some(_) => report.modified.push(path),
+            None => report.extra.push(path),
+        }
+    }
+    report.missing = expected.into_keys().collect();
+    Ok(report)
+}
+
+/// blake3 of every regular file, keyed by its `/`-separated path relative to `dir`.
+/// Symlinks are followed and hashed under their own path, a link loop is an error.
+/// The manifest and signature are skipped when they live inside `dir`
+fn hash_tree(dir: &Path, files: &TreeFiles) -> anyhow::Result<BTreeMap<String, blake3::Hash>> {
+    let skip = [
+        path::absolute(&files.manifest)?,
+        path::absolute(&files.signature)?,
+    ];
+    let mut paths = Vec::new();
+    for entry in WalkDir::new(dir).follow_links(true).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() || skip.contains(&path::absolute(entry.path())?) {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir)?;
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow::anyhow!("path is not valid UTF-8: {}", relative.display()))?
+            .join("/");
+        paths.push((name, entry.into_path()));
+    }
+
+    paths
+        .into_par_iter()
+        .map(|(name, path)| {
+            let hash = blake3::Hasher::new()
+                .update_reader(File::open(&path)?)?
+                .finalize();
+            Ok((name, hash))
+        })
+        .collect()
+}
+
+/// `b3sum` output, sorted by path
+fn format_manifest(hashes: &BTreeMap<String, blake3::Hash>) -> String {
+    hashes
+        .iter()
+        .map(|(name, hash)| format_checksum_line(hash.to_hex().as_str(), name) + "\n")
+        .collect()
+}
+
+fn parse_manifest(manifest: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    manifest
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|line| parse_checksum_line(line).map(|(hash, name)| (name, hash)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn temp_tree() -> TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("sub/b.txt"), "b").unwrap();
+        fs::write(dir.join("back\\slash"), "c").unwrap();
+        tmp
+    }
+
+    #[test]
+    fn test_sign_verify_tree() {
+        let passphrase = PassphraseSource::default();
+        for (format, sk, pk) in [
+            (
+                TextSignFormat::Ed25519,
+                "fixtures/ed25519.sk",
+                "fixtures/ed25519.pk",
+            ),
+            (
+                TextSignFormat::Blake3,
+                "fixtures/blake3.txt",
+                "fixtures/blake3.txt",
+            ),
+        ] {
+            let tmp = temp_tree();
+            let dir = tmp.path();
+            let files = TreeFiles::new(dir, None, None);
+            let count = process_text_sign_tree(dir, &files, sk, format, &passphrase).unwrap();
+            assert_eq!(count, 3);
+            let manifest = fs::read_to_string(&files.manifest).unwrap();
+            assert!(manifest.contains("  sub/b.txt\n"));
+            assert!(manifest.contains("\n\\"));
+            assert!(manifest.contains("  back\\\\slash\n"));
+
+            let report = process_text_verify_tree(dir, &files, pk, format, &passphrase).unwrap();
+            assert!(report.is_valid());
+
+            fs::write(dir.join("a.txt"), "changed").unwrap();
+            fs::remove_file(dir.join("sub/b.txt")).unwrap();
+            fs::write(dir.join("new.txt"), "new").unwrap();
+            let report = process_text_verify_tree(dir, &files, pk, format, &passphrase).unwrap();
+            assert!(!report.is_valid());
+            assert_eq!(report.modified, ["a.txt"]);
+            assert_eq!(report.missing, ["sub/b.txt"]);
+            assert_eq!(report.extra, ["new.txt"]);
+
+            // a modified manifest fails the signature check
+            fs::write(&files.manifest, manifest.replace("sub/b.txt", "sub/c.txt")).unwrap();
+            let report = process_text_verify_tree(dir, &files, pk, format, &passphrase).unwrap();
+            assert!(!report.signature.valid);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tree_follows_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let passphrase = PassphraseSource::default();
+        let (format, key) = (TextSignFormat::Blake3, "fixtures/blake3.txt");
+        let tmp = temp_tree();
+        let dir = tmp.path();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("target.txt"), "t").unwrap();
+        symlink(outside.path().join("target.txt"), dir.join("link.txt")).unwrap();
+        symlink(dir.join("sub"), dir.join("sub-link")).unwrap();
+
+        let files = TreeFiles::new(dir, None, None);
+        let count = process_text_sign_tree(dir, &files, key, format, &passphrase).unwrap();
+        assert_eq!(count, 5);
+        let manifest = fs::read_to_string(&files.manifest).unwrap();
+        assert!(manifest.contains("  link.txt\n"));
+        assert!(manifest.contains("  sub-link/b.txt\n"));
+
+        // swapping the content behind a link is a modification
+        fs::write(outside.path().join("target.txt"), "swapped").unwrap();
+        let report = process_text_verify_tree(dir, &files, key, format, &passphrase).unwrap();
+        assert_eq!(report.modified, ["link.txt"]);
+
+        symlink(dir, dir.join("sub/loop")).unwrap();
+        assert!(process_text_sign_tree(dir, &files, key, format, &passphrase).is_err());
+    }
+
+    #[test]
+    fn test_manifest_escaping() {
+        let mut hashes = BTreeMap::new();
+        hashes.insert("a\\b\nc".to_string(), blake3::hash(b"x"));
+        let manifest = format_manifest(&hashes);
+        let parsed = parse_manifest(&manifest).unwrap();
+        assert_eq!(parsed["a\\b\nc"], blake3::hash(b"x").to_hex().as_str());
+    }
+}
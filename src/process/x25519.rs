@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use age::{
+    armor::{ArmoredReader, ArmoredWriter, Format},
+    x25519::{Identity, Recipient},
+    DecryptError, Decryptor, Encryptor,
+};
+use bech32::{Bech32, Hrp};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+
+use super::{
+    key_crypt::read_key,
+    key_format::{is_public_key_file, load_signing_key, load_verifying_key},
+    text::TextKeyGenerator,
+};
+use crate::{CliError, KeyEncoding, PassphraseSource};
+
+const RECIPIENT_PREFIX: &str = "age1";
+const IDENTITY_PREFIX: &str = "AGE-SECRET-KEY-1";
+
+/// An age X25519 key pair
+pub(crate) struct X25519Key {
+    secret: [u8; 32],
+}
+
+impl X25519Key {
+    /// Reuse an Ed25519 key, the same conversion as libsodium's
+    /// `crypto_sign_ed25519_sk_to_curve25519`
+    pub(crate) fn from_ed25519(key: &SigningKey) -> Self {
+        Self {
+            secret: key.to_scalar_bytes(),
+        }
+    }
+
+    fn identity(&self) -> Identity {
+        let hrp = Hrp::parse("age-secret-key-").expect("HRP is valid");
+        let encoded = bech32::encode::<Bech32>(hrp, &self.secret).expect("key fits in bech32");
+        encoded
+            .to_uppercase()
+            .parse()
+            .expect("encoded identity is valid")
+    }
+
+    /// The identity file, formatted like `age-keygen` output
+    pub(crate) fn to_identity_file(&self) -> String {
+        use age::secrecy::ExposeSecret;
+
+        let identity = self.identity();
+        format!(
+            "# public key: {}\n{}\n",
+            identity.to_public(),
+            identity.to_string().expose_secret()
+        )
+    }
+}
+
+impl TextKeyGenerator for X25519Key {
+    fn generate(encoding: KeyEncoding) -> anyhow::Result<HashMap<&'static str, Vec<u8>>> {
+        if !matches!(encoding, KeyEncoding::Raw) {
+            anyhow::bail!(
+                "{} encoding is not supported for X25519 keys, they are written in age format",
+                encoding
+            );
+        }
+        let mut key = X25519Key { secret: [0u8; 32] };
+        OsRng.fill_bytes(&mut key.secret);
+        let pk = format!("{}\n", key.identity().to_public());
+        let mut map = HashMap::new();
+        map.insert("x25519.sk", key.to_identity_file().into_bytes());
+        map.insert("x25519.pk", pk.into_bytes());
+        Ok(map)
+    }
+}
+
+/// The X25519 public key of an Ed25519 public key, the birational map to Montgomery form
+pub(crate) fn ed25519_recipient(key: &VerifyingKey) -> Recipient {
+    let hrp = Hrp::parse("age").expect("HRP is valid");
+    bech32::encode::<Bech32>(hrp, key.to_montgomery().as_bytes())
+        .expect("key fits in bech32")
+        .parse()
+        .expect("encoded recipient is valid")
+}
+
+/// A recipient given as an `age1...` string, an Ed25519 public key in any supported
+/// encoding, or a file holding either
+pub(crate) fn load_recipient(spec: &str) -> anyhow::Result<Recipient> {
+    let spec = spec.trim();
+    let data = if spec.starts_with(RECIPIENT_PREFIX) {
+        spec.as_bytes().to_vec()
+    } else if Path::new(spec).is_file() {
+        fs::read(spec)?
+    } else {
+        spec.as_bytes().to_vec()
+    };
+
+    let text = String::from_utf8_lossy(&data);
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'));
+    let recipient = match line {
+        Some(line) if line.starts_with(RECIPIENT_PREFIX) => line
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid age recipient {}: {}", line, e)),
+        _ => load_verifying_key(&data).map(|key| ed25519_recipient(&key)),
+    };
+    Ok(recipient.map_err(CliError::InvalidKey)?)
+}
+
+/// The identities of an age identity file, or the converted X25519 key of an Ed25519
+/// secret key. Either may be passphrase-encrypted
+pub(crate) fn load_identities(
+    path: &str,
+    passphrase: &PassphraseSource,
+) -> anyhow::Result<Vec<Identity>> {
+    let data = read_key(path, passphrase)?;
+    let text = String::from_utf8_lossy(&data);
+    let identities = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| l.starts_with(IDENTITY_PREFIX))
+        .map(|l| {
+            l.parse()
+                .map_err(|e| anyhow::anyhow!("invalid age identity: {}", e))
+        })
+        .collect::<anyhow::Result<Vec<Identity>>>()
+        .map_err(CliError::InvalidKey)?;
+    if !identities.is_empty() {
+        return Ok(identities);
+    }
+    if is_public_key_file(path) {
+        return Err(CliError::InvalidKey(anyhow::anyhow!(
+            "{} is a public key, decrypting needs the secret key",
+            path
+        ))
+        .into());
+    }
+    let key = load_signing_key(&data).map_err(CliError::InvalidKey)?;
+    Ok(vec![X25519Key::from_ed25519(&key).identity()])
+}
+
+/// Encrypt to every recipient as an ASCII-armored age file
+pub(crate) fn age_encrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    recipients: &[Recipient],
+) -> anyhow::Result<()> {
+    let encryptor =
+        Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?;
+    let armored = ArmoredWriter::wrap_output(writer, Format::AsciiArmor)?;
+    let mut stream = encryptor.wrap_output(armored)?;
+    io::copy(reader, &mut stream)?;
+    stream.finish()?.finish()?;
+    Ok(())
+}
+
+/// Decrypt an age file, armored or binary
+pub(crate) fn age_decrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    identities: &[Identity],
+) -> anyhow::Result<()> {
+    let decryptor = Decryptor::new(ArmoredReader::new(reader))?;
+    let mut stream = decryptor
+        .decrypt(identities.iter().map(|i| i as &dyn age::Identity))
+        .map_err(|e| match e {
+            DecryptError::NoMatchingKeys => {
+                CliError::InvalidKey(anyhow::anyhow!("the message was not encrypted to this key"))
+                    .into()
+            }
+            e => anyhow::Error::from(CliError::VerificationFailed)
+                .context(format!("decryption failed: {}", e)),
+        })?;
+    // the payload is authenticated chunk by chunk, a bad chunk surfaces as invalid data
+    io::copy(&mut stream, writer).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData => anyhow::Error::from(CliError::VerificationFailed)
+            .context("decryption failed: the message was modified"),
+        _ => e.into(),
+    })?;
+    Ok(())
+}
+
+/// Convert an Ed25519 key to X25519: a secret key becomes an age identity file,
+/// a public key an `age1...` recipient
+pub fn process_text_x25519(key: &str, passphrase: &PassphraseSource) -> anyhow::Result<String> {
+    let data = read_key(key, passphrase)?;
+    if !is_public_key_file(key) {
+        if let Ok(key) = load_signing_key(&data) {
+            return Ok(X25519Key::from_ed25519(&key).to_identity_file());
+        }
+    }
+    let key = load_verifying_key(&data).map_err(CliError::InvalidKey)?;
+    Ok(format!("{}\n", ed25519_recipient(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ed25519_conversion() {
+        let sk = load_signing_key(&fs::read("fixtures/ed25519.sk").unwrap()).unwrap();
+        let identity = X25519Key::from_ed25519(&sk).identity();
+        // converting the public key must give the public key of the converted secret key
+        assert_eq!(
+            ed25519_recipient(&sk.verifying_key()).to_string(),
+            identity.to_public().to_string()
+        );
+        let recipient = load_recipient("fixtures/ed25519_ssh.pub").unwrap();
+        assert!(recipient.to_string().starts_with(RECIPIENT_PREFIX));
+    }
+
+    #[test]
+    fn test_x25519_raw_public_key() {
+        // raw .pk bytes would also load as a secret key, the file name decides
+        let pk = load_verifying_key(&fs::read("fixtures/ed25519.pk").unwrap()).unwrap();
+        let converted = process_text_x25519("fixtures/ed25519.pk", &PassphraseSource::default());
+        assert_eq!(converted.unwrap(), format!("{}\n", ed25519_recipient(&pk)));
+        let Err(err) = load_identities("fixtures/ed25519.pk", &PassphraseSource::default()) else {
+            panic!("a public key must not load as an identity");
+        };
+        assert_eq!(crate::exit_code(&err), crate::EXIT_INVALID_KEY);
+    }
+
+    #[test]
+    fn test_age_encrypt_decrypt() {
+        let keys = X25519Key::generate(KeyEncoding::Raw).unwrap();
+        let sk = String::from_utf8(keys["x25519.sk"].clone()).unwrap();
+        let pk = String::from_utf8(keys["x25519.pk"].clone()).unwrap();
+        let identity: Identity = sk.lines().nth(1).unwrap().parse().unwrap();
+        let ed25519 = load_signing_key(&fs::read("fixtures/ed25519.sk").unwrap()).unwrap();
+
+        // two recipients, either identity can decrypt
+        let recipients = [
+            load_recipient(&pk).unwrap(),
+            ed25519_recipient(&ed25519.verifying_key()),
+        ];
+        let data = vec![42u8; 100_000];
+        let mut encrypted = Vec::new();
+        age_encrypt(&mut &data[..], &mut encrypted, &recipients).unwrap();
+        assert!(encrypted.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----"));
+        for identity in [identity, X25519Key::from_ed25519(&ed25519).identity()] {
+            let mut decrypted = Vec::new();
+            age_decrypt(&mut &encrypted[..], &mut decrypted, &[identity]).unwrap();
+            assert_eq!(decrypted, data);
+        }
+
+        let other = X25519Key::from_ed25519(&SigningKey::generate(&mut OsRng)).identity();
+        let err = age_decrypt(&mut &encrypted[..], &mut Vec::new(), &[other]).unwrap_err();
+        assert_eq!(crate::exit_code(&err), crate::EXIT_INVALID_KEY);
+    }
+}
@@ -1,4 +1,8 @@
-use std::{fs::File, io::Read};
+use std::{
+    fs::{File, OpenOptions},
+    io::{IsTerminal, Read, Write},
+    path::Path,
+};
 
 pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>> {
     let reader: Box<dyn Read> = if input == "-" {
@@ -8,3 +12,50 @@ pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>> {
     };
     Ok(reader)
 }
+
+pub fn get_writer(output: &str) -> anyhow::Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(File::create(output)?)
+    };
+    Ok(writer)
+}
+
+/// Like [`get_writer`], but a newly created file is only readable by its owner
+pub fn get_secret_writer(output: &str) -> anyhow::Result<Box<dyn Write>> {
+    if output == "-" {
+        return get_writer(output);
+    }
+    let file = secret_options().create(true).truncate(true).open(output)?;
+    Ok(Box::new(file))
+}
+
+/// Create a file only readable by its owner, failing if it already exists
+pub fn create_secret_file(path: impl AsRef<Path>) -> anyhow::Result<File> {
+    Ok(secret_options().create_new(true).open(path)?)
+}
+
+fn secret_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+/// Read a secret from the terminal with echo turned off,
+/// or the first line of stdin when it is not a terminal
+pub fn read_secret(prompt: &str) -> anyhow::Result<String> {
+    let secret = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password(prompt)?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    };
+    Ok(secret)
+}